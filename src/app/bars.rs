@@ -1,6 +1,15 @@
-use std::time::SystemTime;
-
-use eframe::egui::{Align, Button, Context, Layout, Response, TextEdit, Ui};
+use eframe::egui::{
+	Align,
+	Button,
+	Color32,
+	Context,
+	Layout,
+	Response,
+	RichText,
+	TextEdit,
+	Ui,
+};
+use time::OffsetDateTime;
 
 use super::NeosPeepsApp;
 use crate::data::Page;
@@ -17,19 +26,26 @@ impl NeosPeepsApp {
 		&mut self, ui: &mut Ui, ctx: &Context, frame: &mut eframe::Frame,
 	) {
 		let is_authenticated =
-			self.runtime.neos_api.as_ref().map_or(false, |a| a.is_authenticated());
+			self.runtime.neos_api.as_ref().is_some_and(|a| a.is_authenticated());
+		let has_data = is_authenticated || self.runtime.stale_since.is_some();
 
 		eframe::egui::menu::bar(ui, |ui| {
 			// View menu
 			ui.menu_button("View", |ui| {
-				if is_authenticated {
+				if has_data {
 					self.add_page_button(ui, "Peeps", Page::Peeps);
 					ui.separator();
 					self.add_page_button(ui, "Sessions", Page::Sessions);
 					ui.separator();
+					self.add_page_button(ui, "Search messages", Page::Search);
+					ui.separator();
 				}
 				self.add_page_button(ui, "Settings", Page::Settings);
 				ui.separator();
+				self.add_page_button(ui, "Logs", Page::Logs);
+				ui.separator();
+				self.add_page_button(ui, "Diagnostics", Page::Diagnostics);
+				ui.separator();
 				self.add_page_button(ui, "About", Page::About);
 				ui.separator();
 				self.add_page_button(ui, "Credits", Page::Credits);
@@ -43,7 +59,14 @@ impl NeosPeepsApp {
 				if is_authenticated {
 					if ui.add(Button::new("Refresh lists")).clicked() {
 						ui.close_menu();
-						self.runtime.last_background_refresh = SystemTime::UNIX_EPOCH;
+						self.threads.schedules.force_all();
+					}
+					if self.runtime.realtime.is_some() {
+						ui.label(if self.realtime_connected() {
+							"Realtime updates connected"
+						} else {
+							"Realtime updates connecting..."
+						});
 					}
 					ui.separator();
 					if ui.add(Button::new("Log out")).clicked() {
@@ -60,17 +83,55 @@ impl NeosPeepsApp {
 
 			ui.separator();
 
+			self.notifications_menu(ui);
+
+			ui.separator();
+
 			ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
 				if ui.button("Quit").clicked() {
 					frame.close();
 				}
+				let unread = self.total_unread();
+				if unread > 0 {
+					ui.label(
+						RichText::new(format!("{unread} unread"))
+							.strong()
+							.color(Color32::LIGHT_RED),
+					);
+				}
 				if self.threads.loading.any() {
-					ui.label("...");
+					ui.label(format!("{} pending", self.threads.loading.count()))
+						.on_hover_text(self.threads.loading.summary());
 				}
 			});
 		});
 	}
 
+	/// Lets the user know that the shown data is from the offline snapshot
+	pub fn stale_banner(
+		&mut self, ui: &mut Ui, ctx: &Context, stale_since: &OffsetDateTime,
+		is_authenticated: bool,
+	) {
+		ui.horizontal(|ui| {
+			ui.colored_label(
+				Color32::YELLOW,
+				format!("Stale since {}", self.runtime.format_time(stale_since)),
+			)
+			.on_hover_text("Showing the last known data until it's refreshed");
+
+			let login_op_in_progress = self.runtime.neos_api.is_none();
+			if !is_authenticated
+				&& ui
+					.add_enabled(!login_op_in_progress, Button::new("Reconnect"))
+					.clicked()
+			{
+				if let Some(user_session) = self.stored.user_session.clone() {
+					self.try_use_session(user_session, ctx);
+				}
+			}
+		});
+	}
+
 	pub fn search_bar(&mut self, ui: &mut Ui) -> Response {
 		let mut resp = None;
 		ui.horizontal(|ui| {
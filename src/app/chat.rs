@@ -1,16 +1,43 @@
 //! The friends page of the app
+use std::{path::Path, time::SystemTime};
+
 use eframe::{
-	egui::{Context, Grid, Key, Label, Layout, ScrollArea, Sense, TextEdit, Ui},
+	egui::{
+		Button,
+		Color32,
+		ComboBox,
+		Context,
+		Grid,
+		Key,
+		Label,
+		Layout,
+		Modifiers,
+		RichText,
+		ScrollArea,
+		Sense,
+		TextEdit,
+		TextStyle,
+		Ui,
+	},
 	emath::Align,
 	epaint::Vec2,
 };
 
 use super::NeosPeepsApp;
+use crate::{
+	data::OlderMessages,
+	export::{ChatExport, ExportFormat},
+	messages::Message,
+	outbox::OutgoingState,
+};
+
+/// The max length of text messages in Neos, counted in characters
+const MAX_MESSAGE_LENGTH: usize = 512;
 
 impl NeosPeepsApp {
 	fn message_row(
-		&self, _ctx: &Context, ui: &mut Ui, width: f32, friend: &neos::Friend,
-		message: &neos::Message,
+		&self, ctx: &Context, ui: &mut Ui, width: f32, friend: &neos::Friend,
+		message: &neos::Message, outgoing: Option<&OutgoingState>,
 	) {
 		ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
 			ui.set_max_width(self.stored.col_min_width.min(
@@ -22,6 +49,26 @@ impl NeosPeepsApp {
 				ui.label(&friend.username);
 			} else {
 				ui.label("You");
+				match outgoing {
+					None => {
+						ui.label(RichText::new("Sent").small());
+					}
+					Some(OutgoingState::Sending) => {
+						ui.label(RichText::new("Sending...").small());
+					}
+					Some(OutgoingState::Failed(err)) => {
+						ui.label(RichText::new("Failed").small().color(Color32::RED))
+							.on_hover_text(err);
+						ui.horizontal(|ui| {
+							if ui.small_button("Retry").clicked() {
+								self.retry_message(ctx, &message.id);
+							}
+							if ui.small_button("Delete").clicked() {
+								self.delete_outgoing(&message.id);
+							}
+						});
+					}
+				}
 			}
 		});
 
@@ -33,20 +80,24 @@ impl NeosPeepsApp {
 			ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
 				match &message.content {
 					neos::MessageContents::Text(content) => {
-						ui.label(content);
+						ui.label(self.rich_text(ui, content, TextStyle::Body, None));
 					}
 					neos::MessageContents::SessionInvite(session) => {
 						ui.label("Invited to session:");
 						if ui
 							.add(
-								Label::new(session.stripped_name())
-									.wrap(true)
-									.sense(Sense::click()),
+								Label::new(self.session_name(
+									ui,
+									session,
+									TextStyle::Body,
+									None,
+								))
+								.wrap(true)
+								.sense(Sense::click()),
 							)
 							.clicked()
 						{
-							*self.runtime.session_window.borrow_mut() =
-								Some((session.id.clone(), Some(*session.clone())));
+							self.open_session(session);
 						}
 					}
 					neos::MessageContents::CreditTransfer(transaction) => {
@@ -55,7 +106,7 @@ impl NeosPeepsApp {
 							ui.label(&transaction.token);
 							ui.label(":");
 						});
-						ui.label(&transaction.amount.to_string());
+						ui.label(transaction.amount.to_string());
 					}
 					neos::MessageContents::Sound(record) => {
 						ui.label("Audio message");
@@ -78,9 +129,22 @@ impl NeosPeepsApp {
 		});
 	}
 
-	pub fn chat_page(&mut self, ctx: &Context, ui: &mut Ui) {
+	/// Opens the chat with the user, dropping the export set up for the
+	/// previous one.
+	pub fn open_chat(&self, user_id: neos::id::User) {
+		// Retried if marking the messages as read failed before
+		self.runtime.marking_read.borrow_mut().remove(&user_id);
+		*self.runtime.open_chat.borrow_mut() =
+			Some((user_id, SystemTime::UNIX_EPOCH));
+		self.runtime.chat_export.borrow_mut().take();
+	}
+
+	pub fn chat_page(&self, ctx: &Context, ui: &mut Ui) {
 		if ui.button("Back").clicked() {
-			*self.runtime.open_chat.borrow_mut() = None;
+			if let Some((user_id, _)) = self.runtime.open_chat.borrow_mut().take() {
+				self.runtime.marking_read.borrow_mut().remove(&user_id);
+			}
+			self.runtime.chat_export.borrow_mut().take();
 		}
 
 		self.check_if_should_refresh_curr(ctx);
@@ -89,73 +153,30 @@ impl NeosPeepsApp {
 			return;
 		};
 
+		if self.unread_count(&friend.id) > 0 {
+			self.mark_read(ctx, &friend.id);
+		}
+
 		let mut send_message = false;
 
 		self.clickable_username(ui, ctx, &friend.id, &friend.username, None, None);
+		if ui.button("Invite to session...").clicked() {
+			self.open_invite_window(friend.id.clone());
+		}
+		self.export_controls(ui, friend);
 
-		if self.threads.loading.messages.get() {
+		if self.threads.loading.messages.is_loading() {
 			ui.label("Loading messages...");
 		}
 
 		ui.with_layout(Layout::bottom_up(Align::Center), |ui| {
 			ui.set_height(ui.available_height());
-			ui.allocate_ui_with_layout(
-				Vec2::new(ui.available_width(), 36f32),
-				Layout::right_to_left(Align::Center),
-				|ui| {
-					if let Some((_, typed_msg, _)) =
-						&mut *self.runtime.open_chat.borrow_mut()
-					{
-						if ui.button("Send").clicked() {
-							send_message = true;
-						}
-						let response = ui.add_sized(
-							ui.available_size(),
-							TextEdit::singleline(typed_msg)
-								.desired_width(ui.available_width()),
-						);
-						if response.lost_focus() && ui.input(|i| i.key_pressed(Key::Enter))
-						{
-							send_message = true;
-						}
-					}
-				},
-			);
+			send_message = self.composer(ui, &friend.id);
 
 			ui.with_layout(Layout::top_down(Align::Center), |ui| {
 				ui.set_height(ui.available_height());
 
-				if let Some(messages) = self.runtime.messages.get(&friend.id) {
-					ScrollArea::vertical()
-						.max_height(ui.available_height())
-						.stick_to_bottom(true)
-						.show_rows(
-							ui,
-							self.stored.row_height,
-							messages.len(),
-							|ui, row_range| {
-								let width = ui.available_width();
-								Grid::new("messages_list_".to_owned() + friend.id.as_ref())
-									.start_row(row_range.start)
-									.striped(true)
-									.min_row_height(self.stored.row_height)
-									.num_columns(2)
-									.show(ui, |ui| {
-										for row in row_range {
-											let message = &messages.get(row);
-											if let Some(message) = message {
-												self.message_row(ctx, ui, width, friend, &message.0);
-											} else {
-												ui.label("An error occurred");
-											}
-											ui.end_row();
-										}
-									});
-							},
-						);
-				} else if !self.threads.loading.messages.get() {
-					ui.label("No messages yet");
-				}
+				self.messages_list(ctx, ui, friend);
 			});
 		});
 
@@ -164,11 +185,174 @@ impl NeosPeepsApp {
 		}
 	}
 
+	fn messages_list(&self, ctx: &Context, ui: &mut Ui, friend: &neos::Friend) {
+		let messages = self.runtime.messages.get(&friend.id);
+		let outgoing = self.outgoing_to(&friend.id);
+		if messages.is_none() && outgoing.is_empty() {
+			if !self.threads.loading.messages.is_loading() {
+				ui.label("No messages yet");
+			}
+			return;
+		}
+		let messages: &[Message] = messages.map_or(&[], |messages| messages);
+
+		let older_messages =
+			self.runtime.older_messages.borrow().get(&friend.id).copied();
+		let row_height = self.stored.row_height + ui.spacing().item_spacing.y;
+		// Such as a message search hit
+		let scroll_to = self
+			.runtime
+			.chat_scroll_to
+			.borrow_mut()
+			.take()
+			.and_then(|id| messages.iter().position(|message| message.0.id == id));
+
+		let mut scroll_area = ScrollArea::vertical()
+			.max_height(ui.available_height())
+			.stick_to_bottom(scroll_to.is_none());
+		if let Some(index) = scroll_to {
+			// After the row for loading older messages
+			#[allow(clippy::cast_precision_loss)]
+			let offset = (index + 1) as f32 * row_height;
+			scroll_area = scroll_area.vertical_scroll_offset(offset);
+		} else if let Some(OlderMessages::Added(added)) = older_messages {
+			// Keeps the same messages in view when older ones are added above
+			#[allow(clippy::cast_precision_loss)]
+			let offset = added as f32 * row_height;
+			scroll_area = scroll_area.vertical_scroll_offset(offset);
+		}
+		if let Some(OlderMessages::Added(_)) = older_messages {
+			self.runtime.older_messages.borrow_mut().remove(&friend.id);
+		}
+
+		// The first row is for the state of loading older messages, and the
+		// unconfirmed sent messages are after the fetched ones.
+		let output = scroll_area.show_rows(
+			ui,
+			self.stored.row_height,
+			1 + messages.len() + outgoing.len(),
+			|ui, row_range| {
+				let width = ui.available_width();
+				Grid::new("messages_list_".to_owned() + friend.id.as_ref())
+					.start_row(row_range.start)
+					.striped(true)
+					.min_row_height(self.stored.row_height)
+					.num_columns(2)
+					.show(ui, |ui| {
+						for row in row_range {
+							if row == 0 {
+								ui.label(match older_messages {
+									Some(OlderMessages::Loading) => "Loading older messages...",
+									Some(OlderMessages::Beginning) => "Beginning of conversation",
+									Some(OlderMessages::NotKept) => {
+										"Older messages aren't kept, see the settings"
+									}
+									_ => "Scroll up for older messages",
+								});
+							} else if let Some(message) = messages.get(row - 1) {
+								self.message_row(ctx, ui, width, friend, &message.0, None);
+							} else if let Some(outgoing) =
+								outgoing.get(row - 1 - messages.len())
+							{
+								self.message_row(
+									ctx,
+									ui,
+									width,
+									friend,
+									&outgoing.message,
+									Some(&outgoing.state),
+								);
+							} else {
+								ui.label("An error occurred");
+							}
+							ui.end_row();
+						}
+					});
+			},
+		);
+
+		// Not when the chat was just opened, as it's at the top before sticking
+		// to the bottom
+		let offset = output.state.offset.y;
+		let previous_offset = self
+			.runtime
+			.chat_offset
+			.replace(Some((friend.id.clone(), offset)))
+			.filter(|(user_id, _)| *user_id == friend.id)
+			.map(|(_, previous_offset)| previous_offset);
+		// The content might also be too short to scroll
+		let scrolled_up = previous_offset.is_some_and(|previous| previous > 0_f32)
+			|| (ui.rect_contains_pointer(output.inner_rect)
+				&& ui.input(|input| input.scroll_delta.y > 0_f32));
+		if offset <= 0_f32 && scrolled_up && older_messages.is_none() {
+			if let Some(oldest) = messages.first() {
+				self.fetch_older_messages(ctx, friend.id.clone(), oldest.0.send_time);
+			}
+		}
+	}
+
+	/// The button for exporting the chat, and the format and file choice
+	/// after it's clicked.
+	fn export_controls(&self, ui: &mut Ui, friend: &neos::Friend) {
+		let mut chat_export = self.runtime.chat_export.borrow_mut();
+		let Some(export) = &mut *chat_export else {
+			if ui.button("Export...").clicked() {
+				*chat_export = Some(ChatExport::new(&friend.username));
+			}
+			return;
+		};
+
+		let mut close = false;
+		ui.horizontal(|ui| {
+			let mut format = export.format;
+			ComboBox::from_id_source("chat_export_format")
+				.selected_text(format.name())
+				.show_ui(ui, |ui| {
+					for option in ExportFormat::ALL {
+						ui.selectable_value(&mut format, option, option.name());
+					}
+				});
+			if format != export.format {
+				export.set_format(format);
+			}
+			if ui.button("Cancel").clicked() {
+				close = true;
+			}
+			let save = if export.replace { "Overwrite" } else { "Save" };
+			if ui.add_enabled(!export.path.is_empty(), Button::new(save)).clicked() {
+				if export.replace || !Path::new(&export.path).exists() {
+					self.export_chat(friend, export.clone());
+					close = true;
+				} else {
+					export.replace = true;
+				}
+			}
+			let response = ui.add(
+				TextEdit::singleline(&mut export.path)
+					.hint_text("File")
+					.desired_width(ui.available_width()),
+			);
+			if response.changed() {
+				export.replace = false;
+			}
+		});
+		if export.replace {
+			ui.colored_label(
+				ui.visuals().warn_fg_color,
+				"The file already exists, overwrite it?",
+			);
+		}
+
+		if close {
+			*chat_export = None;
+		}
+	}
+
 	fn get_curr_chat_friend(&self) -> Option<&neos::Friend> {
 		use rayon::prelude::*;
 
 		let user_id = match &*self.runtime.open_chat.borrow() {
-			Some((id, _, _)) => id.clone(),
+			Some((id, _)) => id.clone(),
 			None => {
 				return None;
 			}
@@ -177,10 +361,10 @@ impl NeosPeepsApp {
 		self.runtime.friends.par_iter().find_any(|friend| friend.id == user_id)
 	}
 
-	fn check_if_should_refresh_curr(&mut self, ctx: &Context) {
-		if !self.threads.loading.messages.get() {
+	fn check_if_should_refresh_curr(&self, ctx: &Context) {
+		if !self.threads.loading.messages.is_loading() {
 			let mut refresh_id = None;
-			if let Some((user_id, _, last_refresh_start)) =
+			if let Some((user_id, last_refresh_start)) =
 				&mut *self.runtime.open_chat.borrow_mut()
 			{
 				let now = std::time::SystemTime::now();
@@ -195,22 +379,79 @@ impl NeosPeepsApp {
 		}
 	}
 
-	fn send_curr_msg(&mut self, ctx: &Context) {
-		if !self.threads.loading.messages.get() {
-			let mut taken_opt: Option<(neos::id::User, String)> = None;
+	/// The text box for the message with a length counter, returns if the
+	/// message should be sent.
+	fn composer(&self, ui: &mut Ui, user_id: &neos::id::User) -> bool {
+		let mut drafts = self.stored.drafts.borrow_mut();
+		let draft = drafts.entry(user_id.clone()).or_default();
+		let mut send = false;
 
-			if let Some(opt) = &mut *self.runtime.open_chat.borrow_mut() {
-				taken_opt = Some((opt.0.clone(), std::mem::take(&mut opt.1)));
+		let length = draft.chars().count();
+		ui.horizontal(|ui| {
+			let counter =
+				RichText::new(format!("{length}/{MAX_MESSAGE_LENGTH}")).small();
+			if length > MAX_MESSAGE_LENGTH {
+				ui.label(counter.color(Color32::RED));
+				ui.label(RichText::new("Too long to send").small().color(Color32::RED));
+			} else if length > MAX_MESSAGE_LENGTH * 9 / 10 {
+				ui.label(counter.color(Color32::YELLOW));
+			} else {
+				ui.label(counter);
 			}
+			ui.label(RichText::new("Shift+Enter for a new line").small().weak());
+		});
 
-			if let Some((user_id, typed_msg)) = taken_opt {
-				let message = neos::Message::new(
-					neos::MessageContents::Text(typed_msg),
-					self.stored.user_session.as_ref().unwrap().user_id.clone(),
-					user_id,
+		ui.allocate_ui_with_layout(
+			Vec2::new(ui.available_width(), 54f32),
+			Layout::right_to_left(Align::Center),
+			|ui| {
+				let id = ui.make_persistent_id("chat_composer");
+				// Shift+Enter is left for the text box, which adds a new line
+				if ui.memory(|m| m.has_focus(id))
+					&& ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Enter))
+				{
+					send = true;
+				}
+				if ui
+					.add_enabled(length <= MAX_MESSAGE_LENGTH, Button::new("Send"))
+					.clicked()
+				{
+					send = true;
+				}
+				ui.add_sized(
+					ui.available_size(),
+					TextEdit::multiline(draft).id(id).desired_width(ui.available_width()),
 				);
-				self.send_message(ctx, message);
-			}
+			},
+		);
+
+		if draft.is_empty() {
+			drafts.remove(user_id);
 		}
+
+		send && length <= MAX_MESSAGE_LENGTH
+	}
+
+	fn send_curr_msg(&self, ctx: &Context) {
+		let Some(user_id) =
+			self.runtime.open_chat.borrow().as_ref().map(|(id, _)| id.clone())
+		else {
+			return;
+		};
+		let mut drafts = self.stored.drafts.borrow_mut();
+		if drafts.get(&user_id).is_none_or(|draft| draft.trim().is_empty()) {
+			return;
+		}
+		let Some(typed_msg) = drafts.remove(&user_id) else {
+			return;
+		};
+		drop(drafts);
+
+		let message = neos::Message::new(
+			neos::MessageContents::Text(typed_msg),
+			self.stored.user_session.as_ref().unwrap().user_id.clone(),
+			user_id,
+		);
+		self.queue_message(ctx, message);
 	}
 }
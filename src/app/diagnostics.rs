@@ -0,0 +1,74 @@
+//! The diagnostics page of the app, for seeing where resources go
+
+use eframe::egui::{Grid, ProgressBar, Ui};
+
+use super::NeosPeepsApp;
+use crate::{
+	data::{Page, Texture},
+	image::cache,
+};
+
+#[allow(clippy::cast_precision_loss)]
+fn mib(bytes: u64) -> f32 { bytes as f32 / cache::MIB as f32 }
+
+impl NeosPeepsApp {
+	pub fn diagnostics_page(&mut self, ui: &mut Ui) {
+		ui.heading("Diagnostics");
+
+		let texture_usage = self.runtime.texture_usage();
+		let texture_budget = self.stored.texture_budget * cache::MIB;
+		let animated = self
+			.runtime
+			.textures
+			.values()
+			.filter(|texture| matches!(***texture, Texture::Animated(_)))
+			.count();
+		let disk_usage = cache::usage();
+
+		ui.add(
+			ProgressBar::new(mib(texture_usage) / mib(texture_budget).max(1_f32))
+				.text(format!(
+					"{:.1} / {:.0} MiB of image memory",
+					mib(texture_usage),
+					mib(texture_budget)
+				)),
+		);
+
+		Grid::new("diagnostics_table").striped(true).num_columns(2).show(
+			ui,
+			|ui| {
+				ui.label("Loaded images");
+				ui.label(format!(
+					"{} ({animated} animated)",
+					self.runtime.textures.len()
+				));
+				ui.end_row();
+
+				ui.label("Loading images");
+				ui.label(format!(
+					"{} ({} queued)",
+					self.runtime.loading_textures.borrow().len(),
+					self.threads.images.queued()
+				));
+				ui.end_row();
+
+				ui.label("Failed images");
+				ui.label(self.runtime.failed_images.len().to_string());
+				ui.end_row();
+
+				ui.label("Image cache on disk");
+				ui.label(format!(
+					"{:.1} / {} MiB in {} files",
+					mib(disk_usage.size),
+					self.stored.image_cache_size,
+					disk_usage.files
+				));
+				ui.end_row();
+			},
+		);
+
+		if ui.button("Back").clicked() {
+			self.stored.page = Page::Peeps;
+		}
+	}
+}
@@ -0,0 +1,108 @@
+//! The window for picking a session to invite a user to
+
+use std::collections::HashSet;
+
+use ahash::RandomState;
+use eframe::egui::{
+	Context,
+	Grid,
+	Id,
+	Label,
+	ScrollArea,
+	TextEdit,
+	TextStyle,
+	Window,
+};
+
+use super::{sessions::session_users_count, NeosPeepsApp};
+
+impl NeosPeepsApp {
+	/// Opens the session picker for inviting the user.
+	pub fn open_invite_window(&self, user_id: neos::id::User) {
+		*self.runtime.invite_window.borrow_mut() = Some((user_id, String::new()));
+	}
+
+	/// The known sessions, those of friends first as they're likelier to be
+	/// the ones to invite to.
+	fn invitable_sessions(&self, search: &str) -> Vec<&neos::SessionInfo> {
+		let search = search.to_lowercase();
+		let mut seen = HashSet::<_, RandomState>::default();
+		self
+			.runtime
+			.friends
+			.iter()
+			.flat_map(|friend| &friend.status.active_sessions)
+			.chain(&self.runtime.sessions)
+			.filter(|session| seen.insert(session.id.as_ref()))
+			.filter(|session| {
+				search.is_empty()
+					|| session.stripped_name().to_lowercase().contains(&search)
+					|| session.host_username.to_lowercase().contains(&search)
+			})
+			.collect()
+	}
+
+	pub fn invite_window(&self, ctx: &Context) {
+		let mut open = true;
+		let mut invite = None;
+		if let Some((user_id, search)) =
+			&mut *self.runtime.invite_window.borrow_mut()
+		{
+			let username = self
+				.runtime
+				.friends
+				.iter()
+				.find(|friend| &friend.id == user_id)
+				.map_or_else(|| user_id.as_ref(), |friend| friend.username.as_str());
+
+			Window::new(format!("Invite {username} to a session"))
+				.open(&mut open)
+				.id(Id::new("invite_window"))
+				.show(ctx, |ui| {
+					ui.add(
+						TextEdit::singleline(search)
+							.hint_text("Search by name or host")
+							.desired_width(ui.available_width()),
+					);
+
+					let sessions = self.invitable_sessions(search);
+					if sessions.is_empty() {
+						ui.label("No sessions found");
+						return;
+					}
+
+					ScrollArea::vertical().max_height(400_f32).show(ui, |ui| {
+						Grid::new("invite_sessions").striped(true).num_columns(4).show(
+							ui,
+							|ui| {
+								for session in sessions {
+									if ui.button("Invite").clicked() {
+										invite = Some(session.clone());
+									}
+									ui.add(
+										Label::new(self.session_name(
+											ui,
+											session,
+											TextStyle::Body,
+											None,
+										))
+										.wrap(true),
+									);
+									ui.label(&session.host_username);
+									session_users_count(ui, session);
+									ui.end_row();
+								}
+							},
+						);
+					});
+				});
+
+			if let Some(session) = &invite {
+				self.send_invite(ctx, user_id.clone(), session);
+			}
+		}
+		if !open || invite.is_some() {
+			*self.runtime.invite_window.borrow_mut() = None;
+		}
+	}
+}
@@ -0,0 +1,119 @@
+//! The logs page of the app
+
+use eframe::egui::{
+	Color32,
+	ComboBox,
+	Grid,
+	Label,
+	RichText,
+	ScrollArea,
+	TextEdit,
+	TextStyle,
+	Ui,
+};
+use log::{Level, LevelFilter};
+
+use super::NeosPeepsApp;
+use crate::{data::Page, logging};
+
+const LEVELS: [LevelFilter; 5] = [
+	LevelFilter::Error,
+	LevelFilter::Warn,
+	LevelFilter::Info,
+	LevelFilter::Debug,
+	LevelFilter::Trace,
+];
+
+const fn level_color(level: Level) -> Color32 {
+	match level {
+		Level::Error => Color32::RED,
+		Level::Warn => Color32::YELLOW,
+		Level::Info => Color32::LIGHT_BLUE,
+		Level::Debug | Level::Trace => Color32::GRAY,
+	}
+}
+
+impl NeosPeepsApp {
+	pub fn logs_page(&mut self, ui: &mut Ui) {
+		ui.heading("Logs");
+
+		let filter = &mut self.runtime.log_filter;
+		ui.horizontal(|ui| {
+			ComboBox::from_id_source("logs_level")
+				.selected_text(filter.level.as_str())
+				.show_ui(ui, |ui| {
+					for level in LEVELS {
+						ui.selectable_value(&mut filter.level, level, level.as_str());
+					}
+				});
+			ui.add(
+				TextEdit::singleline(&mut filter.target)
+					.hint_text("Module")
+					.desired_width(150_f32),
+			);
+			ui.add(TextEdit::singleline(&mut filter.text).hint_text("Filter"));
+		});
+
+		ui.label(
+			RichText::new(format!("Also written to {}", logging::path().display()))
+				.small(),
+		);
+
+		let shown = logging::filtered_entries(&self.runtime.log_filter);
+
+		ui.horizontal(|ui| {
+			if ui.button("Copy shown").clicked() {
+				let text = shown
+					.iter()
+					.rev()
+					.map(|entry| {
+						format!(
+							"{} {} {}: {}",
+							self.runtime.format_time(&entry.time),
+							entry.level,
+							entry.target,
+							entry.message
+						)
+					})
+					.collect::<Vec<String>>()
+					.join("\n");
+				ui.output_mut(|o| o.copied_text = text);
+			}
+			if ui.button("Back").clicked() {
+				self.stored.page = Page::Peeps;
+			}
+		});
+
+		if shown.is_empty() {
+			ui.label("Nothing to show");
+			return;
+		}
+
+		let row_height = ui.text_style_height(&TextStyle::Body);
+		ScrollArea::vertical().max_height(600_f32).show_rows(
+			ui,
+			row_height,
+			shown.len(),
+			|ui, row_range| {
+				Grid::new("logs_table")
+					.striped(true)
+					.start_row(row_range.start)
+					.num_columns(4)
+					.show(ui, |ui| {
+						for entry in &shown[row_range] {
+							ui.label(
+								RichText::new(self.runtime.format_time(&entry.time)).small(),
+							);
+							ui.label(
+								RichText::new(entry.level.as_str())
+									.color(level_color(entry.level)),
+							);
+							ui.label(RichText::new(&entry.target).small());
+							ui.add(Label::new(&entry.message).wrap(false));
+							ui.end_row();
+						}
+					});
+			},
+		);
+	}
+}
@@ -4,15 +4,20 @@ use eframe::egui::{self, Context, TextureOptions};
 
 use crate::{
 	data::{Page, Stored},
-	image::from_dynamic_image,
+	image::{broken_image, from_dynamic_image},
 	threading,
 };
 
 mod about;
 mod bars;
 mod chat;
+mod diagnostics;
+mod invite;
 mod login;
+mod logs;
+mod notifications;
 mod peeps;
+mod search;
 mod sessions;
 mod settings;
 
@@ -50,8 +55,11 @@ impl eframe::App for NeosPeepsApp {
 	/// second. Put your widgets into a `SidePanel`, `TopPanel`, `CentralPanel`,
 	/// `Window` or `Area`.
 	fn update(&mut self, ctx: &Context, frame: &mut eframe::Frame) {
+		self.runtime.frame += 1;
 		let is_authenticated =
-			self.runtime.neos_api.as_ref().map_or(false, |a| a.is_authenticated());
+			self.runtime.neos_api.as_ref().is_some_and(|a| a.is_authenticated());
+		// The last known data can be browsed while offline
+		let has_data = is_authenticated || self.runtime.stale_since.is_some();
 
 		if self.runtime.default_profile_picture.is_none() {
 			let user_img =
@@ -63,28 +71,47 @@ impl eframe::App for NeosPeepsApp {
 				TextureOptions::LINEAR,
 			)));
 		}
+		if self.runtime.broken_image.is_none() {
+			self.runtime.broken_image = Some(Rc::new(ctx.load_texture(
+				"broken-image",
+				broken_image(),
+				TextureOptions::LINEAR,
+			)));
+		}
 
-		if is_authenticated
-			&& self.runtime.last_background_refresh + self.stored.refresh_frequency
-				< SystemTime::now()
-		{
-			self.cull_textures();
-			self.runtime.last_background_refresh = SystemTime::now();
-			self.refresh_friends(ctx);
-			self.refresh_sessions(ctx);
-			self.refresh_messages(ctx);
+		self.manage_realtime(ctx);
+
+		if is_authenticated {
+			self.background_refresh(ctx);
 		}
 
 		self.try_recv(ctx);
+		self.save_due_snapshot(ctx);
+
+		let unread = self.total_unread();
+		if unread != self.runtime.title_unread {
+			self.runtime.title_unread = unread;
+			frame.set_window_title(&if unread > 0 {
+				format!("({unread}) {}", env!("CARGO_PKG_NAME"))
+			} else {
+				env!("CARGO_PKG_NAME").to_owned()
+			});
+		}
 
 		egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
 			self.top_bar(ui, ctx, frame);
 		});
 
+		self.toasts(ctx);
+
 		egui::CentralPanel::default().show(ctx, |ui| {
 			egui::ScrollArea::vertical().show(ui, |ui| {
 				ui.with_layout(egui::Layout::top_down(egui::Align::Center), |ui| {
-					if is_authenticated {
+					if let Some(stale_since) = self.runtime.stale_since {
+						self.stale_banner(ui, ctx, &stale_since, is_authenticated);
+					}
+
+					if has_data {
 						if self.runtime.available_update.is_some() {
 							self.update_window(ctx);
 						}
@@ -94,12 +121,18 @@ impl eframe::App for NeosPeepsApp {
 						if self.runtime.session_window.borrow().is_some() {
 							self.session_window(ctx);
 						}
+						if self.runtime.invite_window.borrow().is_some() {
+							self.invite_window(ctx);
+						}
 
 						match self.stored.page {
 							Page::About => self.about_page(ui),
 							Page::Credits => self.credits_page(ui),
 							Page::License => self.license_page(ui),
+							Page::Logs => self.logs_page(ui),
+							Page::Diagnostics => self.diagnostics_page(ui),
 							Page::Peeps => self.peeps_page(ctx, ui),
+							Page::Search => self.search_page(ui),
 							Page::Sessions => self.sessions_page(ctx, ui),
 							Page::Settings => self.settings_page(ui),
 						}
@@ -108,6 +141,8 @@ impl eframe::App for NeosPeepsApp {
 							Page::About => self.about_page(ui),
 							Page::Credits => self.credits_page(ui),
 							Page::License => self.license_page(ui),
+							Page::Logs => self.logs_page(ui),
+							Page::Diagnostics => self.diagnostics_page(ui),
 							Page::Settings => self.settings_page(ui),
 							_ => self.login_page(ui, ctx),
 						}
@@ -115,10 +150,51 @@ impl eframe::App for NeosPeepsApp {
 				});
 			});
 		});
+
+		// Such as the images of rows that were scrolled out of view
+		for key in self.threads.images.end_frame() {
+			self.runtime.loading_textures.get_mut().remove(&key);
+		}
+		self.enforce_texture_budget();
 	}
 }
 
 impl NeosPeepsApp {
+	/// Starts the background refreshes that are due
+	fn background_refresh(&mut self, ctx: &Context) {
+		if self
+			.threads
+			.schedules
+			.friends
+			.is_due(self.polling_frequency(self.stored.friends_refresh_frequency))
+		{
+			self.threads.schedules.friends.started();
+			self.refresh_friends(ctx);
+		}
+
+		// The sessions of friends come with the friends list
+		if !self.stored.filter_friends_only
+			&& self
+				.threads
+				.schedules
+				.sessions
+				.is_due(self.stored.sessions_refresh_frequency)
+		{
+			self.threads.schedules.sessions.started();
+			self.refresh_sessions(ctx);
+		}
+
+		if self
+			.threads
+			.schedules
+			.messages
+			.is_due(self.polling_frequency(self.stored.messages_refresh_frequency))
+		{
+			self.threads.schedules.messages.started();
+			self.refresh_messages(ctx);
+		}
+	}
+
 	pub fn new(creation_ctx: &eframe::CreationContext<'_>) -> Self {
 		let mut app = Self::default();
 
@@ -126,12 +202,21 @@ impl NeosPeepsApp {
 
 		if let Some(storage) = creation_ctx.storage {
 			app = eframe::get_value(storage, eframe::APP_KEY).unwrap_or_default();
+			app.stored.migrate();
+
+			app.interrupt_outgoing();
+			app.load_snapshot();
+			app.load_message_history();
 
 			if let Some(user_session) = app.stored.user_session.clone() {
 				app.try_use_session(user_session, &creation_ctx.egui_ctx);
 			}
 		}
 
+		crate::image::cache::set_max_size(
+			app.stored.image_cache_size * crate::image::cache::MIB,
+		);
+
 		if app.stored.check_updates
 			&& app.stored.last_update_check_time
 				+ std::time::Duration::from_secs(60 * 60 * 24)
@@ -143,7 +228,7 @@ impl NeosPeepsApp {
 		let min_fps_ctx = creation_ctx.egui_ctx.clone();
 		std::thread::spawn(move || {
 			loop {
-				std::thread::sleep(std::time::Duration::from_millis(1000));
+				std::thread::sleep(std::time::Duration::from_secs(1));
 				min_fps_ctx.request_repaint();
 			}
 		});
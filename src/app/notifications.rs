@@ -0,0 +1,103 @@
+//! Toasts & the history of notifications
+
+use eframe::egui::{
+	Align2,
+	Context,
+	Frame,
+	Id,
+	Label,
+	RichText,
+	ScrollArea,
+	Ui,
+	Vec2,
+};
+
+use super::NeosPeepsApp;
+use crate::notifications::Notification;
+
+/// How many toasts to show at most at the same time
+const MAX_TOASTS: usize = 4;
+
+impl NeosPeepsApp {
+	/// Shows the recent notifications in the corner of the screen
+	pub fn toasts(&self, ctx: &Context) {
+		let recent: Vec<&Notification> = self
+			.runtime
+			.notifications
+			.iter()
+			.take_while(|notification| notification.is_recent())
+			.take(MAX_TOASTS)
+			.collect();
+
+		if recent.is_empty() {
+			return;
+		}
+
+		eframe::egui::Area::new(Id::new("toasts"))
+			.anchor(Align2::RIGHT_BOTTOM, Vec2::new(-8_f32, -8_f32))
+			.show(ctx, |ui| {
+				ui.set_max_width(400_f32);
+				for notification in recent {
+					Frame::popup(ui.style()).show(ui, |ui| {
+						notification_summary(ui, notification);
+					});
+				}
+			});
+	}
+
+	pub fn notifications_menu(&mut self, ui: &mut Ui) {
+		let label = match self.runtime.notifications.unseen {
+			0 => "Notifications".to_owned(),
+			unseen => format!("Notifications ({unseen})"),
+		};
+
+		ui.menu_button(label, |ui| {
+			self.runtime.notifications.unseen = 0;
+
+			if self.runtime.notifications.is_empty() {
+				ui.label("Nothing to show");
+				return;
+			}
+
+			if ui.button("Clear").clicked() {
+				self.runtime.notifications.clear();
+				ui.close_menu();
+				return;
+			}
+
+			ui.separator();
+
+			ScrollArea::vertical().max_height(400_f32).show(ui, |ui| {
+				for notification in self.runtime.notifications.iter() {
+					ui.label(
+						RichText::new(self.runtime.format_time(&notification.time)).small(),
+					);
+					notification_summary(ui, notification);
+					if !notification.details.is_empty() {
+						ui.add(
+							Label::new(RichText::new(&notification.details).small())
+								.wrap(true),
+						);
+					}
+					if ui.button("Copy details").clicked() {
+						ui.output_mut(|o| o.copied_text = notification.copy_text());
+					}
+					ui.separator();
+				}
+			});
+		});
+	}
+}
+
+fn notification_summary(ui: &mut Ui, notification: &Notification) {
+	ui.horizontal_wrapped(|ui| {
+		ui.label(
+			RichText::new(notification.severity.as_ref())
+				.color(notification.severity.color()),
+		);
+		ui.label(&notification.summary);
+		if notification.count > 1 {
+			ui.label(RichText::new(format!("x{}", notification.count)).small());
+		}
+	});
+}
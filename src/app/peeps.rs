@@ -1,6 +1,4 @@
 //! The friends page of the app
-use std::time::SystemTime;
-
 use eframe::egui::{
 	Align,
 	Color32,
@@ -13,16 +11,17 @@ use eframe::egui::{
 	RichText,
 	ScrollArea,
 	Sense,
+	TextStyle,
 	Ui,
 	Vec2,
 	Window,
 };
 
 use super::{sessions::session_users_count, NeosPeepsApp};
-use crate::sessions::find_focused_session;
+use crate::{data::SizeTier, sessions::find_focused_session};
 
 impl NeosPeepsApp {
-	pub fn user_window(&mut self, ctx: &Context) {
+	pub fn user_window(&self, ctx: &Context) {
 		let mut open = true;
 		if let Some((id, user, status)) = &*self.runtime.user_window.borrow() {
 			Window::new(id.as_ref())
@@ -30,7 +29,7 @@ impl NeosPeepsApp {
 				.id(Id::new("user_window"))
 				.vscroll(true)
 				.show(ctx, |ui| {
-					if self.threads.loading.user.get() {
+					if self.threads.loading.user.is_loading() {
 						ui.vertical_centered_justified(|ui| {
 							ui.label("Loading user...");
 						});
@@ -48,7 +47,7 @@ impl NeosPeepsApp {
 
 					ui.separator();
 
-					if self.threads.loading.user_status.get() {
+					if self.threads.loading.user_status.is_loading() {
 						ui.vertical_centered_justified(|ui| {
 							ui.label("Loading user status...");
 						});
@@ -66,14 +65,14 @@ impl NeosPeepsApp {
 				});
 		}
 		if !open {
-			*self.runtime.user_window.borrow_mut() = None;
+			self.close_user_window();
 		}
 	}
 
 	fn user_window_section_user(
 		&self, ui: &mut Ui, ctx: &Context, user: &neos::User,
 	) {
-		let pfp = self.get_pfp(ctx, &user.profile);
+		let pfp = self.get_pfp(ctx, user.profile.as_ref(), SizeTier::Full);
 		let size = pfp.size_vec2();
 		let scaling =
 			(ui.available_height() / size.y).min(ui.available_width() / size.x);
@@ -89,8 +88,10 @@ impl NeosPeepsApp {
 					self.remove_friend(user.id.clone());
 				}
 				if ui.button("Chat").on_hover_text("Read/Send messages").clicked() {
-					*self.runtime.open_chat.borrow_mut() =
-						Some((user.id.clone(), String::new(), SystemTime::UNIX_EPOCH));
+					self.open_chat(user.id.clone());
+				}
+				if ui.button("Invite to session...").clicked() {
+					self.open_invite_window(user.id.clone());
 				}
 			} else if ui
 				.button("Add")
@@ -234,7 +235,7 @@ impl NeosPeepsApp {
 		&self, ctx: &Context, ui: &mut Ui, width: f32, friend: &neos::Friend,
 	) {
 		ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
-			let pfp = self.get_pfp(ctx, &friend.profile);
+			let pfp = self.get_pfp(ctx, friend.profile.as_ref(), SizeTier::Row);
 
 			let response = ui.image(
 				pfp.id(),
@@ -277,6 +278,23 @@ impl NeosPeepsApp {
 						.color(Color32::from_rgb(r, g, b)),
 				);
 
+				let unread = self.unread_count(&friend.id);
+				if unread > 0
+					&& ui
+						.add(
+							Label::new(
+								RichText::new(format!(" {unread} unread "))
+									.strong()
+									.color(Color32::WHITE)
+									.background_color(Color32::from_rgb(200, 40, 40)),
+							)
+							.sense(Sense::click()),
+						)
+						.clicked()
+				{
+					self.open_chat(friend.id.clone());
+				}
+
 				if self.stored.row_height >= 130f32 {
 					let response = if let Some(time) = friend.latest_message_time {
 						ui.add(
@@ -287,8 +305,7 @@ impl NeosPeepsApp {
 					};
 
 					if response.clicked() {
-						*self.runtime.open_chat.borrow_mut() =
-							Some((friend.id.clone(), String::new(), SystemTime::UNIX_EPOCH));
+						self.open_chat(friend.id.clone());
 					}
 				}
 			});
@@ -314,14 +331,13 @@ impl NeosPeepsApp {
 				}
 				if ui
 					.add(
-						Label::new(session.stripped_name())
+						Label::new(self.session_name(ui, session, TextStyle::Body, None))
 							.wrap(true)
 							.sense(Sense::click()),
 					)
 					.clicked()
 				{
-					*self.runtime.session_window.borrow_mut() =
-						Some((session.id.clone(), Some(session.clone())));
+					self.open_session(session);
 				}
 				ui.label(friend.status.current_session_access_level.as_ref());
 				session_users_count(ui, session);
@@ -341,7 +357,7 @@ impl NeosPeepsApp {
 
 	fn user_row(&self, ctx: &Context, ui: &mut Ui, user: &neos::User) {
 		ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
-			let pfp = self.get_pfp(ctx, &user.profile);
+			let pfp = self.get_pfp(ctx, user.profile.as_ref(), SizeTier::Row);
 
 			let response = ui.image(
 				pfp.id(),
@@ -397,7 +413,7 @@ impl NeosPeepsApp {
 			self.search_users(ctx);
 		}
 
-		if self.threads.loading.users.get() {
+		if self.threads.loading.users.is_loading() {
 			ui.vertical_centered_justified(|ui| {
 				ui.label("Searching...");
 			});
@@ -454,11 +470,11 @@ impl NeosPeepsApp {
 
 		self.search_bar(ui);
 
-		if self.threads.loading.friends.get() {
+		if self.threads.loading.friends.is_loading() {
 			ui.vertical_centered_justified(|ui| {
 				ui.label("Refreshing friends list");
 			});
-		} else if self.threads.loading.messages.get() {
+		} else if self.threads.loading.messages.is_loading() {
 			ui.vertical_centered_justified(|ui| {
 				ui.label("Refreshing messages");
 			});
@@ -524,12 +540,7 @@ impl NeosPeepsApp {
 			)
 			.clicked()
 		{
-			self.open_user(
-				ctx,
-				id,
-				user.map(Clone::clone),
-				user_status.map(Clone::clone),
-			);
+			self.open_user(ctx, id, user.cloned(), user_status.cloned());
 		}
 	}
 
@@ -545,12 +556,7 @@ impl NeosPeepsApp {
 			)
 			.clicked()
 		{
-			self.open_user(
-				ctx,
-				id,
-				user.map(Clone::clone),
-				user_status.map(Clone::clone),
-			);
+			self.open_user(ctx, id, user.cloned(), user_status.cloned());
 		}
 	}
 
@@ -560,7 +566,7 @@ impl NeosPeepsApp {
 		if let Some(thumbnail) = &session.thumbnail {
 			ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
 				ui.set_width(ui.available_width());
-				let session_pics = self.load_texture(thumbnail, ctx);
+				let session_pics = self.load_texture(thumbnail, SizeTier::Row, ctx);
 				if let Some(session_pic) = session_pics {
 					let size = session_pic.size_vec2();
 					let scaling =
@@ -568,8 +574,7 @@ impl NeosPeepsApp {
 					let response = ui.image(session_pic.id(), size * scaling);
 
 					if response.interact(Sense::click()).clicked() {
-						*self.runtime.session_window.borrow_mut() =
-							Some((session.id.clone(), Some(session.clone())));
+						self.open_session(session);
 					}
 				}
 			});
@@ -0,0 +1,189 @@
+//! The message search page of the app
+
+use std::collections::HashMap;
+
+use eframe::egui::{ComboBox, Grid, Label, RichText, ScrollArea, TextEdit, Ui};
+
+use super::NeosPeepsApp;
+use crate::{
+	data::Page,
+	messages::{contents_text, ContentKind, SearchFilter, SearchHits},
+};
+
+impl NeosPeepsApp {
+	pub fn search_page(&mut self, ui: &mut Ui) {
+		ui.heading("Search messages");
+		self.search_filters(ui);
+
+		if self.runtime.message_search.is_empty() {
+			ui.label("Enter something to search for");
+			return;
+		}
+		self.search_hits(ui);
+	}
+
+	fn search_filters(&mut self, ui: &mut Ui) {
+		let filter = &mut self.runtime.message_search;
+		ui.horizontal(|ui| {
+			ui.add(TextEdit::singleline(&mut filter.text).hint_text("Text"));
+			ui.add(
+				TextEdit::singleline(&mut filter.sender)
+					.hint_text("Sender")
+					.desired_width(150_f32),
+			);
+			ComboBox::from_id_source("search_kind")
+				.selected_text(filter.kind.map_or("All kinds", ContentKind::name))
+				.show_ui(ui, |ui| {
+					ui.selectable_value(&mut filter.kind, None, "All kinds");
+					for kind in ContentKind::ALL {
+						ui.selectable_value(&mut filter.kind, Some(kind), kind.name());
+					}
+				});
+		});
+		ui.horizontal(|ui| {
+			for (date, hint) in [(&mut filter.from, "From"), (&mut filter.to, "To")] {
+				let invalid =
+					!date.is_empty() && SearchFilter::parse_date(date).is_none();
+				ui.add(
+					TextEdit::singleline(date)
+						.hint_text(format!("{hint} YYYY-MM-DD"))
+						.desired_width(120_f32),
+				)
+				.on_hover_text(if invalid { "Invalid date" } else { "Inclusive" });
+			}
+			if ui.button("Clear").clicked() {
+				*filter = SearchFilter::default();
+			}
+			if ui.button("Back").clicked() {
+				self.stored.page = Page::Peeps;
+			}
+		});
+	}
+
+	/// Searches the messages again if the filter or the data has changed.
+	fn update_search_hits(&mut self) {
+		let filter = &self.runtime.message_search;
+		let revision = self.runtime.data_revision;
+		if self.runtime.search_hits.as_ref().is_some_and(|cached| {
+			cached.filter == *filter && cached.revision == revision
+		}) {
+			return;
+		}
+
+		let names: HashMap<&neos::id::User, &str> = self
+			.runtime
+			.friends
+			.iter()
+			.map(|friend| (&friend.id, friend.username.as_str()))
+			.collect();
+		let matcher = filter.matcher();
+		let mut hits: Vec<(&neos::Message, (neos::id::User, usize, String))> = self
+			.runtime
+			.messages
+			.iter()
+			.flat_map(|(user_id, messages)| {
+				messages
+					.iter()
+					.enumerate()
+					.map(move |(i, message)| (user_id, i, &message.0))
+			})
+			.filter_map(|(user_id, i, message)| {
+				let sender = if message.sender_id == message.owner_id {
+					"You"
+				} else {
+					names
+						.get(&message.sender_id)
+						.copied()
+						.unwrap_or_else(|| message.sender_id.as_ref())
+				};
+				matcher
+					.matches(message, sender)
+					.then(|| (message, (user_id.clone(), i, sender.to_owned())))
+			})
+			.collect();
+		hits.sort_unstable_by_key(|(message, _)| {
+			std::cmp::Reverse(message.send_time)
+		});
+
+		self.runtime.search_hits = Some(SearchHits {
+			filter: filter.clone(),
+			revision,
+			hits: hits.into_iter().map(|(_, hit)| hit).collect(),
+		});
+	}
+
+	/// The messages matching the filter, newest first
+	fn search_hits(&mut self, ui: &mut Ui) {
+		self.update_search_hits();
+		let Some(search_hits) = &self.runtime.search_hits else {
+			return;
+		};
+		let hits = &search_hits.hits;
+
+		ui.label(format!("{} found", hits.len()));
+
+		let mut open = None;
+		// The buttons are the tallest in the rows, the spacing is added by the
+		// scroll area
+		let row_height = ui.spacing().interact_size.y;
+		ScrollArea::vertical().show_rows(
+			ui,
+			row_height,
+			hits.len(),
+			|ui, row_range| {
+				Grid::new("search_hits")
+					.striped(true)
+					.start_row(row_range.start)
+					.min_row_height(row_height)
+					.num_columns(5)
+					.show(ui, |ui| {
+						for (user_id, i, sender) in &hits[row_range] {
+							let Some(message) =
+								self.runtime.messages.get(user_id).and_then(|m| m.get(*i))
+							else {
+								continue;
+							};
+							let message = &message.0;
+							ui.label(
+								RichText::new(self.runtime.format_time(&message.send_time))
+									.small(),
+							);
+							let friend = self
+								.runtime
+								.friends
+								.iter()
+								.find(|friend| friend.id == *user_id);
+							if ui
+								.add_enabled(
+									friend.is_some(),
+									eframe::egui::Button::new("Open"),
+								)
+								.on_disabled_hover_text(
+									"Only the chats of contacts can be opened",
+								)
+								.clicked()
+							{
+								open = Some((user_id.clone(), message.id.clone()));
+							}
+							ui.label(
+								friend
+									.map_or_else(|| user_id.as_ref(), |friend| &friend.username),
+							);
+							ui.label(sender);
+							ui.add(
+								Label::new(contents_text(&message.content).replace('\n', " "))
+									.wrap(false),
+							);
+							ui.end_row();
+						}
+					});
+			},
+		);
+
+		if let Some((user_id, message_id)) = open {
+			self.open_chat(user_id);
+			*self.runtime.chat_scroll_to.borrow_mut() = Some(message_id);
+			self.stored.page = Page::Peeps;
+		}
+	}
+}
@@ -9,12 +9,14 @@ use eframe::egui::{
 	RichText,
 	ScrollArea,
 	Sense,
+	TextStyle,
 	Ui,
 	Vec2,
 	Window,
 };
 
 use super::NeosPeepsApp;
+use crate::data::SizeTier;
 
 pub fn session_users_count(ui: &mut Ui, session: &neos::SessionInfo) {
 	ui.horizontal(|ui| {
@@ -33,7 +35,7 @@ pub fn session_users_count(ui: &mut Ui, session: &neos::SessionInfo) {
 }
 
 impl NeosPeepsApp {
-	pub fn session_window(&mut self, ctx: &Context) {
+	pub fn session_window(&self, ctx: &Context) {
 		let mut open = true;
 		if let Some((id, session)) = &*self.runtime.session_window.borrow() {
 			Window::new(RichText::new(id.as_ref()).small())
@@ -41,7 +43,7 @@ impl NeosPeepsApp {
 				.open(&mut open)
 				.vscroll(true)
 				.show(ctx, |ui| {
-					if self.threads.loading.session.get() {
+					if self.threads.loading.session.is_loading() {
 						ui.vertical_centered_justified(|ui| {
 							ui.label("Loading...");
 						});
@@ -55,7 +57,9 @@ impl NeosPeepsApp {
 
 					if let Some(session) = session {
 						if let Some(asset_url) = &session.thumbnail {
-							if let Some(thumbnail) = self.load_texture(asset_url, ctx) {
+							if let Some(thumbnail) =
+								self.load_texture(asset_url, SizeTier::Full, ctx)
+							{
 								let size = thumbnail.size_vec2();
 								let scaling = (ui.available_height() / size.y)
 									.min(ui.available_width() / size.x);
@@ -65,8 +69,13 @@ impl NeosPeepsApp {
 						ui.horizontal_wrapped(|ui| {
 							session_decorations(ui, session);
 							ui.add(
-								Label::new(RichText::new(session.stripped_name()).heading())
-									.wrap(true),
+								Label::new(self.session_name(
+									ui,
+									session,
+									TextStyle::Heading,
+									None,
+								))
+								.wrap(true),
 							);
 							ui.label(
 								RichText::new(session.access_level.as_ref()).small_raised(),
@@ -133,7 +142,7 @@ impl NeosPeepsApp {
 		}
 
 		if !open {
-			*self.runtime.session_window.borrow_mut() = None;
+			self.close_session_window();
 		}
 	}
 
@@ -153,11 +162,12 @@ impl NeosPeepsApp {
 			ui.horizontal_wrapped(|ui| {
 				if ui
 					.add(
-						Label::new(
-							RichText::new(session.stripped_name())
-								.heading()
-								.color(Color32::WHITE),
-						)
+						Label::new(self.session_name(
+							ui,
+							session,
+							TextStyle::Heading,
+							Some(Color32::WHITE),
+						))
 						.wrap(true)
 						.sense(Sense::click()),
 					)
@@ -199,7 +209,9 @@ impl NeosPeepsApp {
 		ui.with_layout(Layout::top_down(Align::LEFT), |ui| {
 			ui.set_min_width(ui.available_width());
 			if let Some(asset_url) = &session.thumbnail {
-				if let Some(thumbnail) = self.load_texture(asset_url, ctx) {
+				if let Some(thumbnail) =
+					self.load_texture(asset_url, SizeTier::Row, ctx)
+				{
 					let size = thumbnail.size_vec2();
 					let scaling =
 						(ui.available_height() / size.y).min(ui.available_width() / size.x);
@@ -212,8 +224,7 @@ impl NeosPeepsApp {
 		});
 
 		if open_window {
-			*self.runtime.session_window.borrow_mut() =
-				Some((session.id.clone(), Some(session.clone())));
+			self.open_session(session);
 		}
 	}
 
@@ -222,8 +233,10 @@ impl NeosPeepsApp {
 
 		self.search_bar(ui);
 
-		if !self.stored.filter_friends_only && self.threads.loading.sessions.get()
-			|| self.stored.filter_friends_only && self.threads.loading.friends.get()
+		if !self.stored.filter_friends_only
+			&& self.threads.loading.sessions.is_loading()
+			|| self.stored.filter_friends_only
+				&& self.threads.loading.friends.is_loading()
 		{
 			ui.vertical_centered_justified(|ui| {
 				ui.label("Refreshing sessions list");
@@ -1,9 +1,12 @@
 use std::time::Duration;
 
-use eframe::egui::{Slider, Ui};
+use eframe::egui::{ComboBox, DragValue, Slider, Ui};
 
 use super::NeosPeepsApp;
-use crate::data::Page;
+use crate::{
+	data::{MessageRetention, Page, SizeTier},
+	image::cache,
+};
 
 impl NeosPeepsApp {
 	pub fn settings_page(&mut self, ui: &mut Ui) {
@@ -12,19 +15,27 @@ impl NeosPeepsApp {
 		ui.style_mut().spacing.slider_width =
 			50f32.max(ui.available_width() - 250f32);
 
-		{
-			let mut refresh_freq: u64 = self.stored.refresh_frequency.as_secs();
-			if ui
-				.add(
-					Slider::new(&mut refresh_freq, 5..=900)
-						.text("Refresh frequency")
-						.suffix("s"),
-				)
-				.changed()
-			{
-				self.stored.refresh_frequency = Duration::from_secs(refresh_freq);
-			}
-		}
+		refresh_frequency_slider(
+			ui,
+			&mut self.stored.friends_refresh_frequency,
+			"Friends refresh frequency",
+		);
+		refresh_frequency_slider(
+			ui,
+			&mut self.stored.sessions_refresh_frequency,
+			"Sessions refresh frequency",
+		);
+		refresh_frequency_slider(
+			ui,
+			&mut self.stored.messages_refresh_frequency,
+			"Messages refresh frequency",
+		);
+
+		ui.checkbox(
+			&mut self.stored.realtime,
+			"Receive friend and message updates in realtime?",
+		)
+		.on_hover_text("Polls less often while connected to Neos' SignalR hub");
 
 		ui.add(
 			Slider::new(&mut self.stored.row_height, 100_f32..=300_f32)
@@ -40,6 +51,36 @@ impl NeosPeepsApp {
 				.text("Column width"),
 		);
 
+		let response = ui
+			.add(
+				Slider::new(&mut self.stored.row_image_scale, 1_f32..=4_f32)
+					.text("Row image resolution")
+					.suffix("x row height"),
+			)
+			.on_hover_text("Higher uses more memory but looks sharper");
+		if response.drag_released() || (response.changed() && !response.dragged()) {
+			// Reloaded with the new resolution when they're next needed
+			self.runtime.textures.retain(|(_, tier), _| *tier != SizeTier::Row);
+		}
+
+		ui.add(
+			Slider::new(&mut self.stored.texture_budget, 64..=4096)
+				.logarithmic(true)
+				.text("Image memory budget")
+				.suffix("MiB"),
+		)
+		.on_hover_text("The least recently shown images are unloaded past this");
+
+		self.image_cache_settings(ui);
+		self.animation_settings(ui);
+		self.message_history_settings(ui);
+
+		ui.checkbox(
+			&mut self.stored.rich_text,
+			"Show the colours and styles of session names and messages?",
+		)
+		.on_hover_text("Otherwise their formatting tags are stripped");
+
 		ui.checkbox(
 			&mut self.stored.check_updates,
 			"Automatically check for app updates?",
@@ -50,3 +91,143 @@ impl NeosPeepsApp {
 		}
 	}
 }
+
+impl NeosPeepsApp {
+	fn message_history_settings(&mut self, ui: &mut Ui) {
+		let previous_retention = self.stored.message_retention;
+		let retention = &mut self.stored.message_retention;
+		let mut clear = false;
+		ui.horizontal(|ui| {
+			ComboBox::from_label("Message history")
+				.selected_text(match retention {
+					MessageRetention::Unlimited => "Keep all",
+					MessageRetention::MaxAge(_) => "Keep by age",
+					MessageRetention::MaxCount(_) => "Keep by count",
+				})
+				.show_ui(ui, |ui| {
+					ui.selectable_value(
+						retention,
+						MessageRetention::Unlimited,
+						"Keep all",
+					);
+					if ui
+						.selectable_label(
+							matches!(retention, MessageRetention::MaxAge(_)),
+							"Keep by age",
+						)
+						.clicked()
+					{
+						*retention = MessageRetention::MaxAge(365);
+					}
+					if ui
+						.selectable_label(
+							matches!(retention, MessageRetention::MaxCount(_)),
+							"Keep by count",
+						)
+						.clicked()
+					{
+						*retention = MessageRetention::MaxCount(1000);
+					}
+				});
+
+			match retention {
+				MessageRetention::Unlimited => {}
+				MessageRetention::MaxAge(days) => {
+					ui.add(DragValue::new(days).clamp_range(1..=3650).suffix(" days"));
+				}
+				MessageRetention::MaxCount(count) => {
+					ui.add(
+						DragValue::new(count)
+							.clamp_range(10..=100_000)
+							.suffix(" per conversation"),
+					);
+				}
+			}
+
+			if ui
+				.button("Clear history")
+				.on_hover_text("Forgets the saved messages, along with the ones shown")
+				.clicked()
+			{
+				clear = true;
+			}
+		});
+
+		if clear {
+			self.clear_message_history();
+		} else if self.stored.message_retention != previous_retention {
+			// The saved conversations follow when they're next loaded or saved
+			let retention = self.stored.message_retention;
+			for messages in self.runtime.messages.values_mut() {
+				retention.apply_to_messages(messages);
+			}
+			self.runtime.data_revision += 1;
+			self.update_unread_counts();
+			self.save_snapshot();
+		}
+	}
+
+	fn animation_settings(&mut self, ui: &mut Ui) {
+		let mut changed =
+			ui.checkbox(&mut self.stored.animate_images, "Animate images").changed();
+
+		ui.add_enabled_ui(self.stored.animate_images, |ui| {
+			for response in [
+				ui.add(
+					Slider::new(&mut self.stored.max_animation_frames, 2..=500)
+						.text("Max frames per animation"),
+				),
+				ui.add(
+					Slider::new(&mut self.stored.max_animation_size, 4..=512)
+						.logarithmic(true)
+						.text("Max memory per animation")
+						.suffix("MiB"),
+				),
+			] {
+				changed |= response.drag_released()
+					|| (response.changed() && !response.dragged());
+			}
+		});
+
+		if changed {
+			// Reloaded with the new settings when they're next needed
+			self.runtime.textures.clear();
+		}
+	}
+
+	fn image_cache_settings(&mut self, ui: &mut Ui) {
+		let response = ui.add(
+			Slider::new(&mut self.stored.image_cache_size, 16..=4096)
+				.logarithmic(true)
+				.text("Image cache size")
+				.suffix("MiB"),
+		);
+		// Evicts right away, so not while dragging past smaller sizes
+		if response.drag_released() || (response.changed() && !response.dragged()) {
+			cache::set_max_size(self.stored.image_cache_size * cache::MIB);
+		}
+
+		ui.horizontal(|ui| {
+			let usage = cache::usage();
+			#[allow(clippy::cast_precision_loss)]
+			ui.label(format!(
+				"Using {:.1} MiB in {} files",
+				usage.size as f64 / cache::MIB as f64,
+				usage.files
+			));
+			if ui.button("Clear cache").clicked() {
+				cache::clear();
+			}
+		});
+	}
+}
+
+fn refresh_frequency_slider(ui: &mut Ui, frequency: &mut Duration, text: &str) {
+	let mut refresh_freq: u64 = frequency.as_secs();
+	if ui
+		.add(Slider::new(&mut refresh_freq, 5..=900).text(text).suffix("s"))
+		.changed()
+	{
+		*frequency = Duration::from_secs(refresh_freq);
+	}
+}
@@ -0,0 +1,107 @@
+//! The live Neos cloud, via the [`neos`] crate's API client.
+
+use std::sync::Arc;
+
+use minreq::Method;
+use neos::api_client::{AnyNeos, Neos, NeosUnauthenticated, RequestError};
+use time::OffsetDateTime;
+
+use super::{not_authenticated, NeosBackend};
+
+const HUB_URL: &str = "https://api.neos.com/hub";
+
+impl NeosBackend for AnyNeos {
+	fn is_authenticated(&self) -> bool { Self::is_authenticated(self) }
+
+	fn upgrade(&self, user_session: neos::UserSession) -> Arc<dyn NeosBackend> {
+		let api = NeosUnauthenticated::from(self.clone()).upgrade(user_session);
+		Arc::new(Self::Authenticated(api))
+	}
+
+	fn downgrade(&self) -> Arc<dyn NeosBackend> {
+		Arc::new(Self::Unauthenticated(self.clone().into()))
+	}
+
+	fn hub_url(&self) -> String { HUB_URL.to_owned() }
+
+	fn login(
+		&self, credentials: &neos::LoginCredentials,
+	) -> Result<neos::UserSession, RequestError> {
+		NeosUnauthenticated::from(self.clone()).login(credentials)
+	}
+
+	fn extend_session(&self) -> Result<(), RequestError> {
+		self.authenticated().ok_or_else(not_authenticated)?.extend_session()
+	}
+
+	fn logout(&self) -> Result<(), RequestError> {
+		match self {
+			Self::Authenticated(api) => api.logout(),
+			Self::Unauthenticated(_) => Ok(()),
+		}
+	}
+
+	fn get_friends(&self) -> Result<Vec<neos::Friend>, RequestError> {
+		self.authenticated().ok_or_else(not_authenticated)?.get_friends(None)
+	}
+
+	fn add_friend(&self, id: &neos::id::User) -> Result<(), RequestError> {
+		self.authenticated().ok_or_else(not_authenticated)?.add_friend(id)
+	}
+
+	fn remove_friend(&self, id: &neos::id::User) -> Result<(), RequestError> {
+		self.authenticated().ok_or_else(not_authenticated)?.remove_friend(id)
+	}
+
+	fn search_users(&self, name: &str) -> Result<Vec<neos::User>, RequestError> {
+		Neos::search_users(self, name)
+	}
+
+	fn get_user(&self, id: &neos::id::User) -> Result<neos::User, RequestError> {
+		Neos::get_user(self, id.clone())
+	}
+
+	fn get_user_status(
+		&self, id: &neos::id::User,
+	) -> Result<neos::UserStatus, RequestError> {
+		Neos::get_user_status(self, id)
+	}
+
+	fn get_sessions(&self) -> Result<Vec<neos::SessionInfo>, RequestError> {
+		Neos::get_sessions(self)
+	}
+
+	fn get_session(
+		&self, id: &neos::id::Session,
+	) -> Result<neos::SessionInfo, RequestError> {
+		Neos::get_session(self, id)
+	}
+
+	fn get_messages(
+		&self, max_amount: u16, unread_only: bool,
+		from_time: Option<OffsetDateTime>, user: Option<&neos::id::User>,
+	) -> Result<Vec<neos::Message>, RequestError> {
+		self.authenticated().ok_or_else(not_authenticated)?.get_messages(
+			max_amount,
+			unread_only,
+			from_time,
+			user.cloned(),
+		)
+	}
+
+	fn send_message(&self, message: &neos::Message) -> Result<(), RequestError> {
+		self.authenticated().ok_or_else(not_authenticated)?.send_message(message)
+	}
+
+	// Not supported by the neos crate yet
+	fn mark_messages_read(
+		&self, owner_id: &neos::id::User, ids: &[String],
+	) -> Result<(), RequestError> {
+		self.authenticated().ok_or_else(not_authenticated)?.api_request(
+			Method::Patch,
+			&("users/".to_owned() + owner_id.as_ref() + "/messages"),
+			&mut |req| req.with_json(&ids),
+		)?;
+		Ok(())
+	}
+}
@@ -0,0 +1,309 @@
+//! An API at a custom base URL, such as a local stand-in server with canned
+//! responses.
+
+use std::sync::Arc;
+
+use minreq::{Method, Request, Response};
+use neos::api_client::{Neos, RequestError};
+use time::OffsetDateTime;
+
+use super::{not_authenticated, NeosBackend, HUB_URL_ENV};
+
+/// Neos' hub is next to the API, at `/hub` instead of `/api/`
+fn hub_next_to(base_url: &str) -> String {
+	let base_url = base_url.trim_end_matches('/');
+	base_url.strip_suffix("/api").unwrap_or(base_url).to_owned() + "/hub"
+}
+
+#[derive(Clone)]
+pub struct CustomBackend {
+	/// Always ends with a `/`
+	base_url: String,
+	hub_url: String,
+	user_session: Option<neos::UserSession>,
+}
+
+impl CustomBackend {
+	/// The hub is at [`HUB_URL_ENV`] if it's set, or next to the API.
+	pub fn new(base_url: String) -> Self {
+		let hub_url = match std::env::var(HUB_URL_ENV) {
+			Ok(hub_url) if !hub_url.is_empty() => hub_url,
+			_ => hub_next_to(&base_url),
+		};
+
+		Self::with_hub_url(base_url, hub_url)
+	}
+
+	pub fn with_hub_url(mut base_url: String, hub_url: String) -> Self {
+		if !base_url.ends_with('/') {
+			base_url.push('/');
+		}
+
+		Self { base_url, hub_url, user_session: None }
+	}
+
+	fn user_session(&self) -> Result<&neos::UserSession, RequestError> {
+		self.user_session.as_ref().ok_or_else(not_authenticated)
+	}
+
+	fn user_url(&self, path: &str) -> Result<String, RequestError> {
+		Ok("users/".to_owned() + self.user_session()?.user_id.as_ref() + path)
+	}
+}
+
+impl Neos for CustomBackend {
+	fn api_request(
+		&self, method: Method, url: &str,
+		build: &mut dyn FnMut(Request) -> Result<Request, minreq::Error>,
+	) -> Result<Response, RequestError> {
+		let mut req = Request::new(method, self.base_url.clone() + url)
+			.with_header("Accept", "application/json")
+			.with_header("Content-Type", "application/json")
+			.with_header("User-Agent", crate::USER_AGENT)
+			.with_timeout(120);
+
+		if let Some(user_session) = &self.user_session {
+			req = req.with_header("Authorization", user_session.auth_header());
+		}
+
+		let res = build(req)?.send()?;
+
+		if res.status_code < 200 || res.status_code >= 300 {
+			return Err(RequestError::ResponseCode((
+				res.status_code,
+				res.as_str().unwrap_or("").to_owned(),
+			)));
+		}
+
+		Ok(res)
+	}
+}
+
+impl NeosBackend for CustomBackend {
+	fn is_authenticated(&self) -> bool { self.user_session.is_some() }
+
+	fn upgrade(&self, user_session: neos::UserSession) -> Arc<dyn NeosBackend> {
+		Arc::new(Self { user_session: Some(user_session), ..self.clone() })
+	}
+
+	fn downgrade(&self) -> Arc<dyn NeosBackend> {
+		Arc::new(Self { user_session: None, ..self.clone() })
+	}
+
+	fn hub_url(&self) -> String { self.hub_url.clone() }
+
+	fn login(
+		&self, credentials: &neos::LoginCredentials,
+	) -> Result<neos::UserSession, RequestError> {
+		let res = self.api_request(Method::Post, "userSessions", &mut |req| {
+			req.with_json(credentials)
+		})?;
+
+		Ok(res.json()?)
+	}
+
+	fn extend_session(&self) -> Result<(), RequestError> {
+		self.user_session()?;
+		self.api_request(Method::Patch, "userSessions", &mut Ok)?;
+		Ok(())
+	}
+
+	fn logout(&self) -> Result<(), RequestError> {
+		if let Some(user_session) = &self.user_session {
+			self.api_request(
+				Method::Delete,
+				&("userSessions/".to_owned() + user_session.user_id.as_ref()),
+				&mut Ok,
+			)?;
+		}
+		Ok(())
+	}
+
+	fn get_friends(&self) -> Result<Vec<neos::Friend>, RequestError> {
+		let res =
+			self.api_request(Method::Get, &self.user_url("/friends")?, &mut Ok)?;
+		Ok(res.json()?)
+	}
+
+	fn add_friend(&self, id: &neos::id::User) -> Result<(), RequestError> {
+		let owner_id = self.user_session()?.user_id.clone();
+		self.api_request(
+			Method::Put,
+			&self.user_url(&("/friends/".to_owned() + id.as_ref()))?,
+			&mut |req| {
+				req.with_json(&serde_json::json!({
+					"ownerId": owner_id.as_ref(),
+					"friendStatus": neos::FriendStatus::Accepted
+				}))
+			},
+		)?;
+		Ok(())
+	}
+
+	fn remove_friend(&self, id: &neos::id::User) -> Result<(), RequestError> {
+		let owner_id = self.user_session()?.user_id.clone();
+		self.api_request(
+			Method::Delete,
+			&self.user_url(&("/friends/".to_owned() + id.as_ref()))?,
+			&mut |req| {
+				req.with_json(&serde_json::json!({
+					"ownerId": owner_id.as_ref(),
+					"friendStatus": neos::FriendStatus::Ignored
+				}))
+			},
+		)?;
+		Ok(())
+	}
+
+	fn search_users(&self, name: &str) -> Result<Vec<neos::User>, RequestError> {
+		Neos::search_users(self, name)
+	}
+
+	fn get_user(&self, id: &neos::id::User) -> Result<neos::User, RequestError> {
+		Neos::get_user(self, id.clone())
+	}
+
+	fn get_user_status(
+		&self, id: &neos::id::User,
+	) -> Result<neos::UserStatus, RequestError> {
+		Neos::get_user_status(self, id)
+	}
+
+	fn get_sessions(&self) -> Result<Vec<neos::SessionInfo>, RequestError> {
+		Neos::get_sessions(self)
+	}
+
+	fn get_session(
+		&self, id: &neos::id::Session,
+	) -> Result<neos::SessionInfo, RequestError> {
+		Neos::get_session(self, id)
+	}
+
+	fn get_messages(
+		&self, max_amount: u16, unread_only: bool,
+		from_time: Option<OffsetDateTime>, user: Option<&neos::id::User>,
+	) -> Result<Vec<neos::Message>, RequestError> {
+		let res = self.api_request(
+			Method::Get,
+			&self.user_url("/messages")?,
+			&mut |mut req| {
+				if let Some(from_time) = from_time {
+					req = req.with_param("fromTime", from_time.to_string());
+				}
+				if let Some(user) = user {
+					req = req.with_param("user", user.as_ref());
+				}
+				if unread_only {
+					req = req.with_param("unread", "true");
+				}
+
+				Ok(req.with_param("maxItems", max_amount.to_string()))
+			},
+		)?;
+
+		Ok(res.json()?)
+	}
+
+	fn send_message(&self, message: &neos::Message) -> Result<(), RequestError> {
+		self.user_session()?;
+		self.api_request(
+			Method::Post,
+			&("users/".to_owned() + message.recipient_id.as_ref() + "/messages"),
+			&mut |req| req.with_json(message),
+		)?;
+		Ok(())
+	}
+
+	fn mark_messages_read(
+		&self, owner_id: &neos::id::User, ids: &[String],
+	) -> Result<(), RequestError> {
+		self.user_session()?;
+		self.api_request(
+			Method::Patch,
+			&("users/".to_owned() + owner_id.as_ref() + "/messages"),
+			&mut |req| req.with_json(&ids),
+		)?;
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::backend::stand_in::{self, USER_SESSION};
+
+	#[test]
+	fn logs_in_and_fetches_with_the_session() {
+		let (base, received) =
+			stand_in::serve(vec![(200, USER_SESSION), (200, "[]")]);
+		let backend: Arc<dyn NeosBackend> =
+			Arc::new(CustomBackend::new(base + "/api/"));
+		assert!(!backend.is_authenticated());
+
+		let credentials = neos::LoginCredentials::new(
+			neos::LoginCredentialsIdentifier::Username("test".to_owned()),
+			"password",
+		);
+		let user_session = backend.login(&credentials).unwrap();
+		assert_eq!(user_session.user_id.as_ref(), "U-test");
+
+		let login = received.recv().unwrap();
+		assert_eq!(login.request_line, "POST /api/userSessions HTTP/1.1");
+		assert!(login.body.contains("\"password\":\"password\""));
+
+		let backend = backend.upgrade(user_session);
+		assert!(backend.is_authenticated());
+		assert!(backend.get_friends().unwrap().is_empty());
+
+		let friends = received.recv().unwrap();
+		assert_eq!(friends.request_line, "GET /api/users/U-test/friends HTTP/1.1");
+		assert!(friends.has_header("Authorization: neos U-test:secret"));
+	}
+
+	#[test]
+	fn error_statuses_are_errors() {
+		let (base, received) = stand_in::serve(vec![(404, "\"Not found\"")]);
+		let backend: Arc<dyn NeosBackend> =
+			Arc::new(CustomBackend::new(base + "/api/"));
+
+		let res = backend.search_users("nobody");
+		assert!(
+			matches!(res, Err(RequestError::ResponseCode((404, body))) if body.contains("Not found"))
+		);
+		assert!(received
+			.recv()
+			.unwrap()
+			.request_line
+			.starts_with("GET /api/users"));
+	}
+
+	#[test]
+	fn requires_a_session() {
+		let backend: Arc<dyn NeosBackend> =
+			Arc::new(CustomBackend::new("http://127.0.0.1:9/api".to_owned()));
+		assert!(backend.get_friends().is_err());
+		assert!(backend.extend_session().is_err());
+	}
+
+	#[test]
+	fn hub_is_next_to_the_api() {
+		assert_eq!(
+			hub_next_to("http://localhost:8080/api"),
+			"http://localhost:8080/hub"
+		);
+		assert_eq!(
+			hub_next_to("http://localhost:8080/api/"),
+			"http://localhost:8080/hub"
+		);
+	}
+
+	#[test]
+	fn hub_can_be_elsewhere() {
+		let backend = CustomBackend::with_hub_url(
+			"http://localhost:8080/api".to_owned(),
+			"ws://localhost:8081/hub".to_owned(),
+		);
+		assert_eq!(backend.hub_url(), "ws://localhost:8081/hub");
+		assert_eq!(backend.base_url, "http://localhost:8080/api/");
+	}
+}
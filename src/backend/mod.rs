@@ -0,0 +1,97 @@
+//! Abstraction over the Neos API, so that the app can be ran against
+//! something else than the live cloud, such as a local stand-in server.
+
+use std::sync::Arc;
+
+use neos::api_client::{AnyNeos, NeosUnauthenticated, RequestError};
+use time::OffsetDateTime;
+
+mod cloud;
+mod custom;
+#[cfg(test)]
+pub mod stand_in;
+
+pub use custom::CustomBackend;
+
+/// If set, the API requests are sent to this base URL instead of Neos' cloud.
+const API_BASE_ENV: &str = "NEOS_PEEPS_API_BASE";
+/// Overrides the `SignalR` hub URL of a custom API base.
+const HUB_URL_ENV: &str = "NEOS_PEEPS_HUB_URL";
+
+/// The API operations that the app uses.
+///
+/// Authentication is handled by swapping the whole backend, similarly to how
+/// the [`neos`] API client works.
+#[allow(clippy::module_name_repetitions)]
+pub trait NeosBackend: Send + Sync {
+	fn is_authenticated(&self) -> bool;
+
+	/// Creates an authenticated version of the backend. Does not check the user
+	/// session validity.
+	fn upgrade(&self, user_session: neos::UserSession) -> Arc<dyn NeosBackend>;
+
+	/// Creates a version of the backend without authentication.
+	fn downgrade(&self) -> Arc<dyn NeosBackend>;
+
+	/// The `SignalR` hub for realtime updates, a WebSocket URL skips negotiation.
+	fn hub_url(&self) -> String;
+
+	fn login(
+		&self, credentials: &neos::LoginCredentials,
+	) -> Result<neos::UserSession, RequestError>;
+
+	fn extend_session(&self) -> Result<(), RequestError>;
+
+	fn logout(&self) -> Result<(), RequestError>;
+
+	fn get_friends(&self) -> Result<Vec<neos::Friend>, RequestError>;
+
+	fn add_friend(&self, id: &neos::id::User) -> Result<(), RequestError>;
+
+	fn remove_friend(&self, id: &neos::id::User) -> Result<(), RequestError>;
+
+	fn search_users(&self, name: &str) -> Result<Vec<neos::User>, RequestError>;
+
+	fn get_user(&self, id: &neos::id::User) -> Result<neos::User, RequestError>;
+
+	fn get_user_status(
+		&self, id: &neos::id::User,
+	) -> Result<neos::UserStatus, RequestError>;
+
+	fn get_sessions(&self) -> Result<Vec<neos::SessionInfo>, RequestError>;
+
+	fn get_session(
+		&self, id: &neos::id::Session,
+	) -> Result<neos::SessionInfo, RequestError>;
+
+	fn get_messages(
+		&self, max_amount: u16, unread_only: bool,
+		from_time: Option<OffsetDateTime>, user: Option<&neos::id::User>,
+	) -> Result<Vec<neos::Message>, RequestError>;
+
+	fn send_message(&self, message: &neos::Message) -> Result<(), RequestError>;
+
+	/// Marks the messages with the ID's as read
+	fn mark_messages_read(
+		&self, owner_id: &neos::id::User, ids: &[String],
+	) -> Result<(), RequestError>;
+}
+
+/// Creates an unauthenticated backend, pointed to [`API_BASE_ENV`] if it's
+/// set, or the live Neos cloud otherwise.
+pub fn unauthenticated() -> Arc<dyn NeosBackend> {
+	match std::env::var(API_BASE_ENV) {
+		Ok(base_url) if !base_url.is_empty() => {
+			log::info!("Using a custom API base: {base_url}");
+			Arc::new(CustomBackend::new(base_url))
+		}
+		_ => Arc::new(AnyNeos::Unauthenticated(NeosUnauthenticated::new(
+			crate::USER_AGENT.to_owned(),
+		))),
+	}
+}
+
+/// Treated like the API responding with unauthorized.
+fn not_authenticated() -> RequestError {
+	RequestError::ResponseCode((401, "Not authenticated".to_owned()))
+}
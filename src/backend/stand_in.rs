@@ -0,0 +1,91 @@
+//! A stand-in HTTP server with canned responses, for testing the API and hub
+//! clients without the live cloud.
+
+use std::{
+	io::{BufRead, BufReader, Read, Write},
+	net::TcpListener,
+	sync::mpsc,
+};
+
+/// A user session of `U-test`, as returned by logging in
+pub const USER_SESSION: &str = r#"{
+	"userId": "U-test",
+	"token": "secret",
+	"created": "2023-01-01T00:00:00Z",
+	"expire": "2033-01-01T00:00:00Z",
+	"rememberMe": false,
+	"timestamp": "2023-01-01T00:00:00Z"
+}"#;
+
+/// A request received by the stand-in server
+pub struct Received {
+	pub request_line: String,
+	pub headers: Vec<String>,
+	pub body: String,
+}
+
+impl Received {
+	pub fn has_header(&self, header: &str) -> bool {
+		self.headers.iter().any(|received| received == header)
+	}
+}
+
+/// Serves the canned responses in order, one per connection, with `$BASE`
+/// replaced by the server's address. Returns that address, such as
+/// `http://127.0.0.1:1234`, and the received requests.
+pub fn serve(
+	responses: Vec<(u16, &'static str)>,
+) -> (String, mpsc::Receiver<Received>) {
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let base = format!("http://{}", listener.local_addr().unwrap());
+	let (sender, receiver) = mpsc::channel();
+
+	let server_base = base.clone();
+	std::thread::spawn(move || {
+		for (status, body) in responses {
+			let body = body.replace("$BASE", &server_base);
+			let (mut stream, _) = listener.accept().unwrap();
+			let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+			let mut request_line = String::new();
+			reader.read_line(&mut request_line).unwrap();
+			let mut headers = Vec::new();
+			loop {
+				let mut line = String::new();
+				reader.read_line(&mut line).unwrap();
+				let line = line.trim_end().to_owned();
+				if line.is_empty() {
+					break;
+				}
+				headers.push(line);
+			}
+			let len = headers
+				.iter()
+				.find_map(|header| {
+					let (name, value) = header.split_once(':')?;
+					name.eq_ignore_ascii_case("content-length").then(|| value.trim())
+				})
+				.map_or(0, |len| len.parse::<usize>().unwrap());
+			let mut request_body = vec![0; len];
+			reader.read_exact(&mut request_body).unwrap();
+
+			write!(
+				stream,
+				"HTTP/1.1 {status} Canned\r\nContent-Type: application/json\r\n\
+				Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+				body.len()
+			)
+			.unwrap();
+
+			sender
+				.send(Received {
+					request_line: request_line.trim_end().to_owned(),
+					headers,
+					body: String::from_utf8(request_body).unwrap(),
+				})
+				.unwrap();
+		}
+	});
+
+	(base, receiver)
+}
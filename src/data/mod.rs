@@ -1,7 +1,7 @@
-use std::{collections::HashMap, rc::Rc};
+use std::{collections::HashMap, rc::Rc, time::Duration};
 
 use ahash::RandomState;
-use eframe::egui::TextureHandle;
+use eframe::egui::{Context, TextureHandle};
 
 mod runtime;
 mod stored;
@@ -9,9 +9,70 @@ mod stored;
 pub use runtime::*;
 pub use stored::*;
 
-/// [`neos::AssetUrl`] ID's as keys.
-pub type TexturesMap = HashMap<String, Rc<TextureHandle>, RandomState>;
+/// The resolution that an image is loaded with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SizeTier {
+	/// Downscaled for the rows of the lists
+	Row,
+	/// The full resolution, for the windows
+	Full,
+}
+
+/// [`neos::AssetUrl`] ID's with the size tiers
+pub type TextureKey = (String, SizeTier);
+
+/// A loaded image, which can be an animated sequence of frames.
+pub enum Texture {
+	Still(TextureHandle),
+	/// The frames with how long each is shown
+	Animated(Vec<(TextureHandle, Duration)>),
+}
+
+impl Texture {
+	/// The approximate GPU memory used in bytes, as RGBA
+	pub fn size(&self) -> u64 {
+		let frame_size = |texture: &TextureHandle| {
+			let [width, height] = texture.size();
+			width as u64 * height as u64 * 4
+		};
+
+		match self {
+			Self::Still(texture) => frame_size(texture),
+			Self::Animated(frames) => {
+				frames.iter().map(|(texture, _)| frame_size(texture)).sum()
+			}
+		}
+	}
+
+	/// The frame to show now, requesting a repaint for when the next one is
+	/// due.
+	pub fn current_frame(&self, ctx: &Context) -> &TextureHandle {
+		let frames = match self {
+			Self::Still(texture) => return texture,
+			Self::Animated(frames) => frames,
+		};
+
+		let total: Duration = frames.iter().map(|(_, delay)| *delay).sum();
+		let mut position = Duration::from_secs_f64(
+			ctx.input(|i| i.time) % total.as_secs_f64().max(f64::EPSILON),
+		);
+		for (texture, delay) in frames {
+			if position < *delay {
+				ctx.request_repaint_after(delay.saturating_sub(position));
+				return texture;
+			}
+			position -= *delay;
+		}
+
+		&frames[0].0
+	}
+}
+
+/// Each size tier of an image has its own texture.
+pub type TexturesMap = HashMap<TextureKey, Rc<Texture>, RandomState>;
 
 pub type UserWindow =
 	(neos::id::User, Option<neos::User>, Option<neos::UserStatus>);
 pub type SessionWindow = (neos::id::Session, Option<neos::SessionInfo>);
+/// The recipient of the invite, and the sessions' search text
+pub type InviteWindow = (neos::id::User, String);
@@ -3,47 +3,124 @@ use std::{
 	collections::{HashMap, HashSet},
 	rc::Rc,
 	sync::Arc,
-	time::SystemTime,
+	time::{Duration, Instant, SystemTime},
 };
 
 use ahash::RandomState;
 use eframe::egui::{Context, TextureHandle, TextureOptions};
-use neos::{
-	api_client::{AnyNeos, NeosUnauthenticated},
-	AssetUrl,
-};
+use neos::AssetUrl;
 use time::{format_description::FormatItem, OffsetDateTime};
 
-use super::{SessionWindow, TexturesMap, UserWindow, DEFAULT_TIME_FORMAT};
+use super::{
+	InviteWindow,
+	SessionWindow,
+	SizeTier,
+	Texture,
+	TextureKey,
+	TexturesMap,
+	UserWindow,
+	DEFAULT_TIME_FORMAT,
+};
 use crate::{
 	app::NeosPeepsApp,
+	backend::NeosBackend,
+	image::{cache::MIB, AnimationLimits, DecodeOptions},
 	messages::AllMessages,
+	notifications::{Notification, Notifications},
+	realtime::Realtime,
+	threading::images::Priority,
 	updating::GiteaReleasesResponse,
 };
 
+/// The first retry cooldown of a failed image, doubled on each failure
+const IMAGE_RETRY_COOLDOWN_MIN: Duration = Duration::from_secs(10);
+const IMAGE_RETRY_COOLDOWN_MAX: Duration = Duration::from_secs(1800);
+
+/// An image that failed to load, which isn't retried until the cooldown is
+/// over.
+#[derive(Debug)]
+pub struct ImageFailure {
+	pub failures: u32,
+	pub retry_at: Instant,
+}
+
+/// The paging of a conversation's older messages, which can be loaded if
+/// there's no state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OlderMessages {
+	Loading,
+	/// This many were added to the top, which the scroll position is moved by
+	Added(usize),
+	/// The API returned the last of the messages
+	Beginning,
+	/// The older messages were forgotten right away due to the message
+	/// retention
+	NotKept,
+}
+
 #[allow(clippy::module_name_repetitions)]
 pub struct RuntimeOnly {
 	pub password: String,
 	pub totp: String,
 	pub default_profile_picture: Option<Rc<TextureHandle>>,
-	pub neos_api: Option<Arc<AnyNeos>>,
+	/// Shown in place of images that failed to load
+	pub broken_image: Option<Rc<TextureHandle>>,
+	pub neos_api: Option<Arc<dyn NeosBackend>>,
 	pub friends: Vec<neos::Friend>,
 	/// Searched users.
 	pub users: Vec<neos::User>,
 	pub sessions: Vec<neos::SessionInfo>,
 	pub messages: AllMessages,
-	pub last_background_refresh: SystemTime,
 	pub textures: TexturesMap,
-	used_textures: RefCell<HashSet<String, RandomState>>,
-	pub loading_textures: RefCell<HashSet<String, RandomState>>,
+	/// Counts the drawn frames, for knowing which textures were used recently
+	pub frame: u64,
+	/// The frames during which the textures were last drawn
+	texture_last_used: RefCell<HashMap<TextureKey, u64, RandomState>>,
+	pub loading_textures: RefCell<HashSet<TextureKey, RandomState>>,
+	/// By [`neos::AssetUrl`] ID's, as all the size tiers use the same asset
+	pub failed_images: HashMap<String, ImageFailure, RandomState>,
 	pub user_window: RefCell<Option<UserWindow>>,
 	pub session_window: RefCell<Option<SessionWindow>>,
-	pub open_chat: RefCell<Option<(neos::id::User, String, SystemTime)>>,
+	pub invite_window: RefCell<Option<InviteWindow>>,
+	/// With when it was last refreshed
+	pub open_chat: RefCell<Option<(neos::id::User, SystemTime)>>,
+	pub older_messages:
+		RefCell<HashMap<neos::id::User, OlderMessages, RandomState>>,
+	/// The unread messages per user, updated when the messages change
+	pub unread: HashMap<neos::id::User, usize, RandomState>,
+	/// The conversations that are being marked as read, or failed to be
+	pub marking_read: RefCell<HashSet<neos::id::User, RandomState>>,
+	/// Of the window title, so that it's only set when changed
+	pub title_unread: usize,
 	pub available_update: Option<GiteaReleasesResponse>,
 	pub time_format: Vec<FormatItem<'static>>,
+	pub notifications: Notifications,
+	pub realtime: Option<Realtime>,
+	/// When the shown data is from an offline snapshot
+	pub stale_since: Option<OffsetDateTime>,
+	/// When the changed data should be saved to the offline snapshot
+	pub snapshot_due: Option<Instant>,
+	pub log_filter: crate::logging::Filter,
+	pub message_search: crate::messages::SearchFilter,
+	pub search_hits: Option<crate::messages::SearchHits>,
+	/// Changed along with the messages or friends, for knowing when what's
+	/// derived from them is outdated
+	pub data_revision: u64,
+	/// The scroll position of the chat on the previous frame, for noticing
+	/// when the user scrolls to the top
+	pub chat_offset: RefCell<Option<(neos::id::User, f32)>>,
+	/// The ID of a message that the chat should be scrolled to
+	pub chat_scroll_to: RefCell<Option<String>>,
+	/// The export being set up for the open chat
+	pub chat_export: RefCell<Option<crate::export::ChatExport>>,
 }
 
 impl RuntimeOnly {
+	/// The approximate GPU memory used by the loaded textures in bytes
+	pub fn texture_usage(&self) -> u64 {
+		self.textures.values().map(|texture| texture.size()).sum()
+	}
+
 	pub fn format_time(&self, time: &OffsetDateTime) -> String {
 		time
 			.format(&self.time_format)
@@ -53,73 +130,223 @@ impl RuntimeOnly {
 
 impl Default for RuntimeOnly {
 	fn default() -> Self {
-		let api = NeosUnauthenticated::new(crate::USER_AGENT.to_owned());
-
 		Self {
 			totp: String::default(),
 			password: String::default(),
 			default_profile_picture: Option::default(),
-			neos_api: Some(Arc::new(AnyNeos::Unauthenticated(api))),
+			broken_image: Option::default(),
+			neos_api: Some(crate::backend::unauthenticated()),
 			friends: Vec::default(),
 			users: Vec::default(),
 			sessions: Vec::default(),
 			messages: AllMessages::default(),
-			last_background_refresh: SystemTime::UNIX_EPOCH,
 			textures: HashMap::default(),
-			used_textures: RefCell::default(),
+			frame: 0,
+			texture_last_used: RefCell::default(),
 			loading_textures: RefCell::default(),
+			failed_images: HashMap::default(),
 			user_window: RefCell::default(),
 			session_window: RefCell::default(),
+			invite_window: RefCell::default(),
 			open_chat: RefCell::default(),
+			older_messages: RefCell::default(),
+			unread: HashMap::default(),
+			marking_read: RefCell::default(),
+			title_unread: 0,
 			available_update: None,
 			time_format: DEFAULT_TIME_FORMAT.to_owned(),
+			notifications: Notifications::default(),
+			realtime: None,
+			stale_since: None,
+			snapshot_due: None,
+			log_filter: crate::logging::Filter::default(),
+			message_search: crate::messages::SearchFilter::default(),
+			search_hits: None,
+			data_revision: 0,
+			chat_scroll_to: RefCell::default(),
+			chat_offset: RefCell::default(),
+			chat_export: RefCell::default(),
 		}
 	}
 }
 
 impl NeosPeepsApp {
-	pub fn cull_textures(&mut self) {
-		let used_textures =
-			std::mem::take(&mut self.runtime.used_textures).into_inner();
-		self.runtime.textures.retain(|id, _| used_textures.contains(id));
+	/// Evicts the least recently drawn textures until within the budget,
+	/// never the ones drawn during the current frame.
+	pub fn enforce_texture_budget(&mut self) {
+		let budget = self.stored.texture_budget * MIB;
+		let mut usage = self.runtime.texture_usage();
+
+		let textures = &mut self.runtime.textures;
+		let loading = self.runtime.loading_textures.get_mut();
+		let last_used = self.runtime.texture_last_used.get_mut();
+		last_used
+			.retain(|key, _| textures.contains_key(key) || loading.contains(key));
+
+		if usage <= budget {
+			return;
+		}
+
+		let mut by_use: Vec<(TextureKey, u64)> = textures
+			.keys()
+			.map(|key| (key.clone(), last_used.get(key).copied().unwrap_or(0)))
+			.collect();
+		by_use.sort_unstable_by_key(|(_, frame)| *frame);
+
+		for (key, frame) in by_use {
+			if usage <= budget || frame >= self.runtime.frame {
+				break;
+			}
+			if let Some(texture) = textures.remove(&key) {
+				usage -= texture.size();
+			}
+			last_used.remove(&key);
+		}
 	}
 
 	pub fn load_texture(
-		&self, asset_url: &AssetUrl, ctx: &Context,
-	) -> Option<Rc<TextureHandle>> {
-		self.runtime.used_textures.borrow_mut().insert(asset_url.id().to_owned());
-		if let Some(texture) = self.runtime.textures.get(asset_url.id()) {
-			return Some(texture.clone());
+		&self, asset_url: &AssetUrl, tier: SizeTier, ctx: &Context,
+	) -> Option<TextureHandle> {
+		let key = (asset_url.id().to_owned(), tier);
+		self
+			.runtime
+			.texture_last_used
+			.borrow_mut()
+			.insert(key.clone(), self.runtime.frame);
+		if let Some(texture) = self.runtime.textures.get(&key) {
+			return Some(texture.current_frame(ctx).clone());
 		}
-		self.start_retrieving_image(asset_url.clone(), ctx.clone());
+		if let Some(failure) = self.runtime.failed_images.get(&key.0) {
+			let remaining =
+				failure.retry_at.saturating_duration_since(Instant::now());
+			if !remaining.is_zero() {
+				ctx.request_repaint_after(remaining);
+				return self.runtime.broken_image.as_deref().cloned();
+			}
+		}
+		self.start_retrieving_image(asset_url.clone(), key, ctx.clone());
 
 		None
 	}
 
-	/// Starts a thread to start retrieving the image if wasn't already.
-	fn start_retrieving_image(&self, asset_url: AssetUrl, ctx: Context) {
-		if !self
+	/// Records a failed image, doubling its retry cooldown each time. Only the
+	/// first failure is notified about, as the retries would flood them.
+	pub fn image_failed(&mut self, id: String, err: String) {
+		let now = Instant::now();
+		// Those not retried in a while aren't shown anymore
+		self.runtime.failed_images.retain(|_, failure| {
+			now.saturating_duration_since(failure.retry_at) < IMAGE_RETRY_COOLDOWN_MAX
+		});
+
+		let failure = self
 			.runtime
-			.loading_textures
-			.borrow_mut()
-			.insert(asset_url.id().to_string())
-		{
+			.failed_images
+			.entry(id)
+			.or_insert(ImageFailure { failures: 0, retry_at: now });
+		if failure.failures == 0 {
+			self
+				.runtime
+				.notifications
+				.push(Notification::warning("Failed to fetch image", err));
+		} else {
+			log::warn!("Failed to fetch image again: {err}");
+		}
+		let cooldown = IMAGE_RETRY_COOLDOWN_MIN
+			.saturating_mul(2_u32.saturating_pow(failure.failures))
+			.min(IMAGE_RETRY_COOLDOWN_MAX);
+		failure.failures += 1;
+		failure.retry_at = now + cooldown;
+	}
+
+	/// The size in pixels that images of the tier are downscaled to fit in.
+	fn max_image_dimension(&self, ctx: &Context, tier: SizeTier) -> Option<u32> {
+		match tier {
+			SizeTier::Row => {
+				let screen_size = ctx.screen_rect().size().max_elem();
+				let points = (self.stored.row_height * self.stored.row_image_scale)
+					.min(screen_size);
+				#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+				Some((points * ctx.pixels_per_point()).ceil() as u32)
+			}
+			SizeTier::Full => None,
+		}
+	}
+
+	/// Queues retrieving the image if it wasn't already.
+	fn start_retrieving_image(
+		&self, asset_url: AssetUrl, key: TextureKey, ctx: Context,
+	) {
+		let priority = match key.1 {
+			SizeTier::Row => Priority::Row,
+			SizeTier::Full => Priority::Window,
+		};
+		if !self.runtime.loading_textures.borrow_mut().insert(key.clone()) {
+			self.threads.images.touch(&key, priority);
 			return;
 		}
+		let options = DecodeOptions {
+			max_dimension: self.max_image_dimension(&ctx, key.1),
+			animation: self.stored.animate_images.then(|| AnimationLimits {
+				max_frames: self.stored.max_animation_frames,
+				max_bytes: self.stored.max_animation_size * MIB,
+			}),
+		};
 		let image_sender = self.threads.channels.image_sender();
-		self.threads.spawn_data_op(move || {
-			match crate::image::retrieve(&asset_url) {
-				Ok(image) => {
-					let image = crate::image::from_dynamic_image(&image);
-					let image =
-						ctx.load_texture(asset_url.id(), image, TextureOptions::LINEAR);
-					image_sender.send((asset_url.id().to_owned(), Some(image))).unwrap();
-				}
-				Err(err) => {
-					image_sender.send((asset_url.id().to_owned(), None)).unwrap();
-					eprintln!("Failed to fetch image! {err}");
+		self.threads.images.queue(key.clone(), priority, move || {
+			match crate::image::retrieve(&asset_url, options) {
+				Ok(frames) => {
+					let name = format!("{}@{:?}", asset_url.id(), key.1);
+					let mut frames: Vec<(TextureHandle, Duration)> = frames
+						.into_iter()
+						.enumerate()
+						.map(|(i, (image, delay))| {
+							let image = crate::image::from_dynamic_image(&image);
+							let name = format!("{name}#{i}");
+							(ctx.load_texture(name, image, TextureOptions::LINEAR), delay)
+						})
+						.collect();
+					let texture = if frames.len() > 1 {
+						Texture::Animated(frames)
+					} else {
+						Texture::Still(frames.remove(0).0)
+					};
+					image_sender.send((key, Ok(texture))).unwrap();
 				}
+				Err(err) => image_sender.send((key, Err(err))).unwrap(),
 			}
 		});
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_the_first_image_failure_is_notified() {
+		let mut app = NeosPeepsApp::default();
+		app.image_failed("broken".to_owned(), "Failed".to_owned());
+		app.image_failed("broken".to_owned(), "Failed".to_owned());
+
+		assert_eq!(app.runtime.notifications.iter().count(), 1);
+		assert_eq!(app.runtime.failed_images["broken"].failures, 2);
+	}
+
+	#[test]
+	fn long_unretried_image_failures_are_pruned() {
+		let mut app = NeosPeepsApp::default();
+		app.runtime.failed_images.insert(
+			"forgotten".to_owned(),
+			ImageFailure {
+				failures: 1,
+				retry_at: Instant::now()
+					.checked_sub(IMAGE_RETRY_COOLDOWN_MAX * 2)
+					.unwrap(),
+			},
+		);
+		app.image_failed("broken".to_owned(), "Failed".to_owned());
+
+		assert!(!app.runtime.failed_images.contains_key("forgotten"));
+		assert!(app.runtime.failed_images.contains_key("broken"));
+	}
+}
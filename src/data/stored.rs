@@ -1,4 +1,8 @@
-use std::time::{Duration, SystemTime};
+use std::{
+	cell::RefCell,
+	collections::HashMap,
+	time::{Duration, SystemTime},
+};
 
 use serde::{Deserialize, Serialize};
 use time::format_description::FormatItem;
@@ -11,33 +15,75 @@ pub const DEFAULT_TIME_FORMAT: &[FormatItem<'static>] =
 
 #[derive(Serialize, Deserialize)]
 #[serde(default)]
+#[allow(clippy::struct_excessive_bools)]
 pub struct Stored {
 	pub check_updates: bool,
 	pub last_update_check_time: SystemTime,
 	pub user_session: Option<neos::UserSession>,
 	pub identifier: neos::LoginCredentialsIdentifier,
-	pub refresh_frequency: Duration,
+	pub friends_refresh_frequency: Duration,
+	/// The public sessions list, which is large and changes slowly
+	pub sessions_refresh_frequency: Duration,
+	pub messages_refresh_frequency: Duration,
+	/// The single interval of older versions, moved to the ones above on load
+	#[serde(skip_serializing)]
+	refresh_frequency: Option<Duration>,
+	/// If to connect to Neos' `SignalR` hub for realtime updates
+	pub realtime: bool,
 	pub page: Page,
 	pub row_height: f32,
 	pub col_min_width: f32,
+	/// The images in rows are downscaled to this many times the row height
+	pub row_image_scale: f32,
+	/// The max size of the on-disk image cache in MiB
+	pub image_cache_size: u64,
+	/// The max GPU memory of the loaded images in MiB
+	pub texture_budget: u64,
+	/// If to play animated images, instead of showing their first frame
+	pub animate_images: bool,
+	/// If to show the colours and styles of session names and messages,
+	/// instead of stripping their tags
+	pub rich_text: bool,
+	/// Longer animations are cut short
+	pub max_animation_frames: usize,
+	/// The max memory of the frames of a single animation in MiB
+	pub max_animation_size: u64,
+	/// Modified from the UI, so in a cell
+	pub outbox: RefCell<Vec<crate::outbox::OutgoingMessage>>,
+	/// The unsent messages typed to each user
+	pub drafts: RefCell<HashMap<neos::id::User, String>>,
+	/// How many of the messages are kept on disk
+	pub message_retention: MessageRetention,
 	pub filter_friends_only: bool,
 	pub filter_search: String,
-	/// For formats, see https://time-rs.github.io/book/api/format-description.html
+	/// For formats, see <https://time-rs.github.io/book/api/format-description.html>
 	pub time_format: String,
 }
 
-#[derive(Serialize, Deserialize, PartialEq, Eq)]
+#[derive(
+	Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default,
+)]
+pub enum MessageRetention {
+	#[default]
+	Unlimited,
+	/// In days
+	MaxAge(u32),
+	/// Per conversation
+	MaxCount(usize),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Default)]
 pub enum Page {
 	About,
 	Credits,
+	#[default]
 	Peeps,
 	Sessions,
+	Search,
 	Settings,
 	License,
-}
-
-impl Default for Page {
-	fn default() -> Self { Self::Peeps }
+	Logs,
+	Diagnostics,
 }
 
 impl Default for Stored {
@@ -47,13 +93,60 @@ impl Default for Stored {
 			check_updates: false,
 			user_session: None,
 			identifier: neos::LoginCredentialsIdentifier::Username(String::default()),
-			refresh_frequency: Duration::from_secs(120),
+			friends_refresh_frequency: Duration::from_secs(120),
+			sessions_refresh_frequency: Duration::from_secs(300),
+			messages_refresh_frequency: Duration::from_secs(60),
+			refresh_frequency: None,
+			realtime: false,
 			page: Page::default(),
 			row_height: 150_f32,
 			col_min_width: 200f32,
+			row_image_scale: 2_f32,
+			image_cache_size: crate::image::cache::DEFAULT_MAX_SIZE_MIB,
+			texture_budget: 512,
+			animate_images: true,
+			rich_text: true,
+			max_animation_frames: 120,
+			max_animation_size: 64,
+			outbox: RefCell::default(),
+			drafts: RefCell::default(),
+			message_retention: MessageRetention::default(),
 			filter_friends_only: true,
 			filter_search: String::new(),
 			time_format: DEFAULT_TIME_FORMAT_STR.to_owned(),
 		}
 	}
 }
+
+impl Stored {
+	/// Carries settings of older versions over to the ones replacing them.
+	pub const fn migrate(&mut self) {
+		if let Some(frequency) = self.refresh_frequency.take() {
+			self.friends_refresh_frequency = frequency;
+			self.sessions_refresh_frequency = frequency;
+			self.messages_refresh_frequency = frequency;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn old_refresh_frequency_is_kept() {
+		let mut stored: Stored = serde_json::from_str(
+			r#"{"refresh_frequency": {"secs": 45, "nanos": 0}}"#,
+		)
+		.unwrap();
+		stored.migrate();
+
+		let frequency = Duration::from_secs(45);
+		assert_eq!(stored.friends_refresh_frequency, frequency);
+		assert_eq!(stored.sessions_refresh_frequency, frequency);
+		assert_eq!(stored.messages_refresh_frequency, frequency);
+		assert!(!serde_json::to_string(&stored)
+			.unwrap()
+			.contains("\"refresh_frequency\""));
+	}
+}
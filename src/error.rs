@@ -0,0 +1,72 @@
+//! Errors from the background operations
+
+use neos::api_client::RequestError;
+
+/// A categorized [`RequestError`], so that the app can react to it.
+#[derive(Debug, Clone)]
+pub enum PeepsError {
+	/// Couldn't reach the API or the request timed out
+	Network(RequestError),
+	/// The user session isn't valid anymore
+	AuthExpired(RequestError),
+	/// The user isn't allowed to access the resource, such as a private one
+	Forbidden(RequestError),
+	/// Too many requests were sent
+	RateLimited(RequestError),
+	/// The response couldn't be decoded
+	Decode(RequestError),
+	/// The requested resource doesn't exist
+	NotFound(RequestError),
+	/// The API responded with some other failure
+	Other(RequestError),
+}
+
+impl PeepsError {
+	pub const fn source_err(&self) -> &RequestError {
+		match self {
+			Self::Network(err)
+			| Self::AuthExpired(err)
+			| Self::Forbidden(err)
+			| Self::RateLimited(err)
+			| Self::Decode(err)
+			| Self::NotFound(err)
+			| Self::Other(err) => err,
+		}
+	}
+}
+
+impl From<RequestError> for PeepsError {
+	fn from(err: RequestError) -> Self {
+		match &err {
+			RequestError::ResponseCode((401, _)) => Self::AuthExpired(err),
+			RequestError::ResponseCode((403, _)) => Self::Forbidden(err),
+			RequestError::ResponseCode((404, _)) => Self::NotFound(err),
+			RequestError::ResponseCode((429, _)) => Self::RateLimited(err),
+			RequestError::ResponseCode((408 | 502..=504, _))
+			| RequestError::Other(_) => Self::Network(err),
+			RequestError::Deserialization(_) => Self::Decode(err),
+			RequestError::ResponseCode(_) => Self::Other(err),
+		}
+	}
+}
+
+impl std::fmt::Display for PeepsError {
+	fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+		let kind = match self {
+			Self::Network(_) => "Network error",
+			Self::AuthExpired(_) => "Authentication expired",
+			Self::Forbidden(_) => "Forbidden",
+			Self::RateLimited(_) => "Rate limited",
+			Self::Decode(_) => "Invalid response",
+			Self::NotFound(_) => "Not found",
+			Self::Other(_) => "Request failed",
+		};
+		write!(f, "{kind} - {}", self.source_err())
+	}
+}
+
+impl std::error::Error for PeepsError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		Some(self.source_err())
+	}
+}
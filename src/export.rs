@@ -0,0 +1,331 @@
+//! Exporting conversations to files, for archiving them outside of the app.
+
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	path::{Path, PathBuf},
+};
+
+use ahash::RandomState;
+use time::{format_description::FormatItem, OffsetDateTime};
+
+use crate::{
+	app::NeosPeepsApp,
+	history::saved_conversation,
+	messages::{contents_text, ContentKind},
+	notifications::Notification,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+	Text,
+	Json,
+	Html,
+}
+
+impl ExportFormat {
+	pub const ALL: [Self; 3] = [Self::Text, Self::Json, Self::Html];
+
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Text => "Plain text",
+			Self::Json => "JSON",
+			Self::Html => "HTML",
+		}
+	}
+
+	pub const fn extension(self) -> &'static str {
+		match self {
+			Self::Text => "txt",
+			Self::Json => "json",
+			Self::Html => "html",
+		}
+	}
+}
+
+/// The export that's being set up in the chat page
+#[derive(Debug, Clone)]
+pub struct ChatExport {
+	pub format: ExportFormat,
+	pub path: String,
+	/// Set after warning that the file exists, so that saving again replaces it
+	pub replace: bool,
+}
+
+impl ChatExport {
+	/// Defaults to a file named after the conversation in the documents dir.
+	pub fn new(username: &str) -> Self {
+		let format = ExportFormat::Text;
+		let dir = directories_next::UserDirs::new()
+			.and_then(|dirs| dirs.document_dir().map(ToOwned::to_owned))
+			.unwrap_or_else(|| crate::DATA_DIR.clone());
+		let name: String = username
+			.chars()
+			.map(|c| if c.is_alphanumeric() || c == '-' { c } else { '_' })
+			.collect();
+		let path =
+			dir.join(format!("chat_{name}")).with_extension(format.extension());
+
+		Self { format, path: path.display().to_string(), replace: false }
+	}
+
+	/// Changes the format, along with the file extension of the path.
+	pub fn set_format(&mut self, format: ExportFormat) {
+		let path = PathBuf::from(&self.path).with_extension(format.extension());
+		self.path = path.display().to_string();
+		self.format = format;
+		self.replace = false;
+	}
+}
+
+/// What's needed for writing a conversation in a background thread
+struct Transcript {
+	username: String,
+	user_id: neos::id::User,
+	messages: Vec<neos::Message>,
+	time_format: Vec<FormatItem<'static>>,
+}
+
+impl Transcript {
+	/// Keeps the latter of the messages with the same ID, sorted by when they
+	/// were sent.
+	fn set_messages(&mut self, messages: impl Iterator<Item = neos::Message>) {
+		let by_id: HashMap<String, neos::Message, RandomState> =
+			messages.map(|message| (message.id.clone(), message)).collect();
+		self.messages = by_id.into_values().collect();
+		self.messages.sort_by_key(|message| message.send_time);
+	}
+
+	fn format_time(&self, time: &OffsetDateTime) -> String {
+		time
+			.format(&self.time_format)
+			.unwrap_or_else(|_| "Time format err".to_string())
+	}
+
+	fn sender(&self, message: &neos::Message) -> &str {
+		if message.sender_id == self.user_id {
+			&self.username
+		} else {
+			"You"
+		}
+	}
+
+	fn to_text(&self) -> String {
+		let mut text = format!("Conversation with {}\n\n", self.username);
+		for message in &self.messages {
+			writeln!(
+				text,
+				"[{}] {}: {}",
+				self.format_time(&message.send_time),
+				self.sender(message),
+				contents_text(&message.content)
+			)
+			.ok();
+		}
+		text
+	}
+
+	fn to_json(&self) -> Result<String, serde_json::Error> {
+		let messages: Vec<serde_json::Value> = self
+			.messages
+			.iter()
+			.map(|message| {
+				serde_json::json!({
+					"time": self.format_time(&message.send_time),
+					"sender": self.sender(message),
+					"kind": ContentKind::of(&message.content).name(),
+					"text": contents_text(&message.content),
+					"message": message,
+				})
+			})
+			.collect();
+
+		serde_json::to_string_pretty(&serde_json::json!({
+			"username": self.username,
+			"user_id": self.user_id,
+			"messages": messages,
+		}))
+	}
+
+	fn to_html(&self) -> String {
+		let title = format!("Conversation with {}", escape_html(&self.username));
+		let mut html = format!(
+			"<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+			<title>{title}</title>\n<style>\n{HTML_STYLE}</style>\n</head>\n\
+			<body>\n<h1>{title}</h1>\n"
+		);
+		for message in &self.messages {
+			write!(
+				html,
+				"<div class=\"message\">\n<span class=\"time\">{}</span> \
+				<span class=\"sender\">{}</span>\n\
+				<div class=\"content\">{}</div>\n</div>\n",
+				escape_html(&self.format_time(&message.send_time)),
+				escape_html(self.sender(message)),
+				html_contents(&message.content)
+			)
+			.ok();
+		}
+		html.push_str("</body>\n</html>\n");
+		html
+	}
+}
+
+const HTML_STYLE: &str =
+	"body { font-family: sans-serif; max-width: 50em; margin: auto; }
+.message { border-bottom: 1px solid #ccc; padding: 0.5em 0; }
+.time { color: #666; font-size: small; }
+.sender { font-weight: bold; }
+.content { white-space: pre-wrap; }
+";
+
+fn escape_html(text: &str) -> String {
+	text.chars().fold(String::with_capacity(text.len()), |mut escaped, c| {
+		match c {
+			'&' => escaped.push_str("&amp;"),
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'"' => escaped.push_str("&quot;"),
+			'\'' => escaped.push_str("&#39;"),
+			_ => escaped.push(c),
+		}
+		escaped
+	})
+}
+
+fn html_link(url: &str) -> String {
+	let url = escape_html(url);
+	format!("<a href=\"{url}\">{url}</a>")
+}
+
+fn html_contents(contents: &neos::MessageContents) -> String {
+	match contents {
+		neos::MessageContents::Sound(record) => {
+			format!("Audio message: {}", html_link(&record.asset_uri.to_string()))
+		}
+		neos::MessageContents::Object(record) => format!(
+			"Record: {} - {} {}",
+			escape_html(&record.name),
+			escape_html(&record.description),
+			html_link(&record.asset_uri.to_string())
+		),
+		_ => escape_html(&contents_text(contents)),
+	}
+}
+
+/// Doesn't replace an existing file unless asked to.
+fn write_file(
+	path: &Path, contents: &str, replace: bool,
+) -> Result<(), String> {
+	if !replace && path.exists() {
+		return Err(format!("{} already exists", path.display()));
+	}
+	crate::atomic_write(path, contents.as_bytes())
+}
+
+impl NeosPeepsApp {
+	/// Writes the conversation with the friend to the file in a background
+	/// thread, including the saved messages that aren't loaded.
+	pub fn export_chat(&self, friend: &neos::Friend, export: ChatExport) {
+		let loaded: Vec<neos::Message> = self
+			.runtime
+			.messages
+			.get(&friend.id)
+			.map(|messages| messages.iter().map(|m| m.0.clone()).collect())
+			.unwrap_or_default();
+		let owner_id =
+			self.stored.user_session.as_ref().map(|session| session.user_id.clone());
+		let mut transcript = Transcript {
+			username: friend.username.clone(),
+			user_id: friend.id.clone(),
+			messages: Vec::new(),
+			time_format: self.runtime.time_format.clone(),
+		};
+
+		let notifications_sender = self.threads.channels.notifications_sender();
+		self.threads.spawn_data_op(move || {
+			// Failing to read them would silently cut the export short
+			let res = owner_id
+				.map_or_else(
+					|| Ok(Vec::new()),
+					|owner_id| saved_conversation(&owner_id, &transcript.user_id),
+				)
+				.map(|saved| transcript.set_messages(saved.into_iter().chain(loaded)))
+				.and_then(|()| match export.format {
+					ExportFormat::Text => Ok(transcript.to_text()),
+					ExportFormat::Json => transcript.to_json().map_err(|e| e.to_string()),
+					ExportFormat::Html => Ok(transcript.to_html()),
+				})
+				.and_then(|contents| {
+					write_file(Path::new(&export.path), &contents, export.replace)
+				});
+
+			let notification = match res {
+				Ok(()) => Notification::info(
+					format!("Exported the chat with {}", transcript.username),
+					export.path,
+				),
+				Err(err) => Notification::error(
+					format!("Failed to export the chat with {}", transcript.username),
+					err,
+				),
+			};
+			notifications_sender.send(notification).ok();
+		});
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn existing_file_is_only_replaced_when_asked() {
+		let path = std::env::temp_dir()
+			.join(format!("neos_peeps_export_{}.txt", std::process::id()));
+		std::fs::write(&path, "earlier").unwrap();
+
+		assert!(write_file(&path, "new", false).is_err());
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "earlier");
+
+		write_file(&path, "new", true).unwrap();
+		assert_eq!(std::fs::read_to_string(&path).unwrap(), "new");
+		std::fs::remove_file(&path).ok();
+	}
+
+	#[test]
+	fn saved_and_loaded_messages_are_merged() {
+		let message = |id: &str, content: &str, sent: &str| -> neos::Message {
+			serde_json::from_value(serde_json::json!({
+				"id": id,
+				"ownerId": "U-test",
+				"senderId": "U-friend",
+				"recipientId": "U-test",
+				"messageType": "Text",
+				"content": content,
+				"sendTime": sent,
+				"lastUpdateTime": sent
+			}))
+			.unwrap()
+		};
+		let mut transcript = Transcript {
+			username: "Friend".to_owned(),
+			user_id: neos::id::User::try_from("U-friend").unwrap(),
+			messages: Vec::new(),
+			time_format: Vec::new(),
+		};
+		let saved = [
+			message("MSG-new", "Saved", "2023-05-10T12:00:00Z"),
+			message("MSG-old", "Old", "2023-05-01T12:00:00Z"),
+		];
+		let loaded = [message("MSG-new", "Loaded", "2023-05-10T12:00:00Z")];
+		transcript.set_messages(saved.into_iter().chain(loaded));
+
+		let contents: Vec<String> = transcript
+			.messages
+			.iter()
+			.map(|message| contents_text(&message.content))
+			.collect();
+		assert_eq!(contents, ["Old", "Loaded"]);
+	}
+}
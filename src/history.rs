@@ -0,0 +1,285 @@
+//! The messages of each conversation saved on disk, so that their whole
+//! history is kept across restarts.
+
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	path::{Path, PathBuf},
+	sync::{Mutex, PoisonError},
+};
+
+use ahash::RandomState;
+use time::OffsetDateTime;
+
+use crate::{
+	app::NeosPeepsApp,
+	data::MessageRetention,
+	messages::{AllMessages, UserMessages},
+	notifications::Notification,
+};
+
+/// A conversation's messages by their ID's
+type Conversation = HashMap<String, neos::Message, RandomState>;
+
+/// Conversations are read, merged with the new messages and written back, so
+/// only one write can happen at a time.
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// The saved conversations of the user
+fn user_dir(user_id: &neos::id::User) -> PathBuf {
+	crate::DATA_DIR.join("messages").join(file_name(user_id.as_ref()))
+}
+
+/// Escapes the characters that might not be allowed in file names
+fn file_name(id: &str) -> String {
+	id.chars().fold(String::new(), |mut name, c| {
+		if c.is_ascii_alphanumeric() || c == '-' {
+			name.push(c);
+		} else {
+			write!(name, "_{:x}", u32::from(c)).ok();
+		}
+		name
+	})
+}
+
+fn read_conversation(path: &Path) -> Result<Conversation, String> {
+	match std::fs::read(path) {
+		Ok(bytes) => serde_json::from_slice(&bytes).map_err(|e| e.to_string()),
+		// Not having saved the conversation yet is fine
+		Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+			Ok(Conversation::default())
+		}
+		Err(err) => Err(err.to_string()),
+	}
+}
+
+/// The saved messages with the user, in the logged in user's history.
+pub fn saved_conversation(
+	owner_id: &neos::id::User, user_id: &neos::id::User,
+) -> Result<Vec<neos::Message>, String> {
+	let path = user_dir(owner_id).join(file_name(user_id.as_ref()));
+	read_conversation(&path.with_extension("json"))
+		.map(|conversation| conversation.into_values().collect())
+}
+
+fn write_conversation(
+	path: &Path, conversation: &Conversation,
+) -> Result<(), String> {
+	let bytes = serde_json::to_vec(conversation).map_err(|e| e.to_string())?;
+	crate::atomic_write(path, &bytes)
+}
+
+impl MessageRetention {
+	/// The send time of the oldest message to keep, if any are forgotten.
+	fn cutoff(
+		self, send_times: impl Iterator<Item = OffsetDateTime>,
+	) -> Option<OffsetDateTime> {
+		match self {
+			Self::Unlimited => None,
+			Self::MaxAge(days) => {
+				Some(OffsetDateTime::now_utc() - time::Duration::days(i64::from(days)))
+			}
+			Self::MaxCount(count) => {
+				let mut send_times: Vec<OffsetDateTime> = send_times.collect();
+				if send_times.len() <= count {
+					return None;
+				}
+				send_times.sort_unstable_by(|a, b| b.cmp(a));
+				Some(send_times[count.max(1) - 1])
+			}
+		}
+	}
+
+	/// Forgets the messages that are too old or past the max count.
+	fn apply(self, conversation: &mut Conversation) {
+		if let Some(cutoff) =
+			self.cutoff(conversation.values().map(|message| message.send_time))
+		{
+			conversation.retain(|_, message| message.send_time >= cutoff);
+		}
+	}
+
+	/// Same as for the saved conversations, but for the ones in memory.
+	pub fn apply_to_messages(self, messages: &mut UserMessages) {
+		if let Some(cutoff) =
+			self.cutoff(messages.iter().map(|message| message.0.send_time))
+		{
+			messages.retain(|message| message.0.send_time >= cutoff);
+		}
+	}
+}
+
+impl NeosPeepsApp {
+	/// Merges the messages into the saved conversations in a background
+	/// thread.
+	pub fn store_messages(&self, messages: &AllMessages) {
+		let Some(user_session) = &self.stored.user_session else {
+			return;
+		};
+		if messages.is_empty() {
+			return;
+		}
+
+		let dir = user_dir(&user_session.user_id);
+		let retention = self.stored.message_retention;
+		let conversations: Vec<(neos::id::User, Vec<neos::Message>)> = messages
+			.iter()
+			.map(|(user_id, messages)| {
+				(user_id.clone(), messages.iter().map(|m| m.0.clone()).collect())
+			})
+			.collect();
+
+		let generation = self.threads.generations.history.clone();
+		let current = generation.current();
+		let notifications_sender = self.threads.channels.notifications_sender();
+		self.threads.spawn_data_op(move || {
+			let _lock = WRITE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+			// The history was cleared while waiting in the queue
+			if !generation.is_current(current) {
+				return;
+			}
+			if let Err(err) = std::fs::create_dir_all(&dir) {
+				notifications_sender
+					.send(Notification::warning(
+						"Failed to save messages",
+						err.to_string(),
+					))
+					.ok();
+				return;
+			}
+
+			for (user_id, messages) in conversations {
+				let path = dir.join(file_name(user_id.as_ref())).with_extension("json");
+				let res = read_conversation(&path).and_then(|mut conversation| {
+					let len = conversation.len();
+					let mut changed = false;
+					for message in messages {
+						if conversation.get(&message.id) != Some(&message) {
+							conversation.insert(message.id.clone(), message);
+							changed = true;
+						}
+					}
+					retention.apply(&mut conversation);
+					// Such as when the open chat is refreshed without anything new
+					if !changed && conversation.len() == len {
+						return Ok(());
+					}
+					write_conversation(&path, &conversation)
+				});
+
+				if let Err(err) = res {
+					notifications_sender
+						.send(Notification::warning(
+							format!("Failed to save messages with {}", user_id.as_ref()),
+							err,
+						))
+						.ok();
+				}
+			}
+		});
+	}
+
+	/// Loads the saved conversations of the logged in user in a background
+	/// thread.
+	pub fn load_message_history(&self) {
+		let Some(user_session) = &self.stored.user_session else {
+			return;
+		};
+
+		let dir = user_dir(&user_session.user_id);
+		let retention = self.stored.message_retention;
+		let history_sender = self.threads.channels.history_sender();
+		let generation = self.threads.generations.history.current();
+		self.threads.spawn_data_op(move || {
+			// Not having saved any messages yet is fine
+			let Ok(read_dir) = std::fs::read_dir(&dir) else {
+				return;
+			};
+
+			let mut messages = Vec::new();
+			for entry in read_dir.flatten() {
+				let path = entry.path();
+				if path.extension().is_none_or(|ext| ext != "json") {
+					continue;
+				}
+				match read_conversation(&path) {
+					Ok(mut conversation) => {
+						retention.apply(&mut conversation);
+						messages.extend(conversation.into_values());
+					}
+					Err(err) => {
+						log::warn!("Failed to load messages {} - {err}", path.display());
+					}
+				}
+			}
+
+			history_sender.send((generation, Self::split_by_user(messages))).ok();
+		});
+	}
+
+	/// Forgets the conversations of the logged in user, both the saved ones
+	/// and the ones in memory.
+	pub fn clear_message_history(&mut self) {
+		let Some(user_session) = &self.stored.user_session else {
+			return;
+		};
+
+		// Saves that were queued before clearing aren't done anymore
+		self.threads.generations.history.invalidate();
+		let dir = user_dir(&user_session.user_id);
+		let notifications_sender = self.threads.channels.notifications_sender();
+		self.threads.spawn_data_op(move || {
+			let _lock = WRITE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+			match std::fs::remove_dir_all(dir) {
+				Ok(()) => log::info!("Cleared the message history"),
+				Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+				Err(err) => {
+					notifications_sender
+						.send(Notification::warning(
+							"Failed to clear message history",
+							err.to_string(),
+						))
+						.ok();
+				}
+			}
+		});
+
+		self.runtime.messages.clear();
+		self.runtime.older_messages.get_mut().clear();
+		self.runtime.data_revision += 1;
+		self.update_unread_counts();
+		// Without the messages
+		self.save_snapshot_now();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn send_times(days_ago: &[i64]) -> impl Iterator<Item = OffsetDateTime> + '_ {
+		let now = OffsetDateTime::now_utc();
+		days_ago.iter().map(move |days| now - time::Duration::days(*days))
+	}
+
+	#[test]
+	fn max_count_keeps_the_latest() {
+		let cutoff =
+			MessageRetention::MaxCount(2).cutoff(send_times(&[5, 1, 3, 2])).unwrap();
+		let kept = send_times(&[5, 1, 3, 2]).filter(|time| *time >= cutoff).count();
+		assert_eq!(kept, 2);
+
+		assert!(MessageRetention::MaxCount(4)
+			.cutoff(send_times(&[5, 1, 3, 2]))
+			.is_none());
+	}
+
+	#[test]
+	fn max_age_and_unlimited() {
+		let cutoff = MessageRetention::MaxAge(3).cutoff(send_times(&[])).unwrap();
+		assert!(send_times(&[2]).all(|time| time >= cutoff));
+		assert!(send_times(&[4]).all(|time| time < cutoff));
+
+		assert!(MessageRetention::Unlimited.cutoff(send_times(&[1000])).is_none());
+	}
+}
@@ -0,0 +1,183 @@
+//! A size bounded on-disk cache of the fetched assets, evicting the least
+//! recently used ones first.
+
+use std::{
+	collections::HashMap,
+	fs::File,
+	path::PathBuf,
+	sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+	time::SystemTime,
+};
+
+use ahash::RandomState;
+
+pub const MIB: u64 = 1024 * 1024;
+pub const DEFAULT_MAX_SIZE_MIB: u64 = 512;
+
+static CACHE: LazyLock<Mutex<DiskCache>> =
+	LazyLock::new(|| Mutex::new(DiskCache::load(crate::TEMP_DIR.clone())));
+
+struct Entry {
+	size: u64,
+	last_access: SystemTime,
+}
+
+struct DiskCache {
+	dir: PathBuf,
+	/// File names as keys
+	entries: HashMap<String, Entry, RandomState>,
+	total_size: u64,
+	max_size: u64,
+}
+
+/// How much the cache is currently using
+#[derive(Debug, Clone, Copy)]
+pub struct Usage {
+	pub size: u64,
+	pub files: usize,
+}
+
+impl DiskCache {
+	/// Indexes the existing files, using their modification time as the last
+	/// access time as that's updated when they're read.
+	fn load(dir: PathBuf) -> Self {
+		let mut cache = Self {
+			dir,
+			entries: HashMap::default(),
+			total_size: 0,
+			max_size: DEFAULT_MAX_SIZE_MIB * MIB,
+		};
+
+		let Ok(read_dir) = std::fs::read_dir(&cache.dir) else {
+			return cache;
+		};
+
+		for dir_entry in read_dir.flatten() {
+			let Ok(metadata) = dir_entry.metadata() else {
+				continue;
+			};
+			if !metadata.is_file() {
+				continue;
+			}
+			let Some(filename) = dir_entry.file_name().to_str().map(str::to_owned)
+			else {
+				continue;
+			};
+			// Left over from an interrupted write
+			if dir_entry.path().extension().is_some_and(|ext| ext == crate::TMP_EXT) {
+				std::fs::remove_file(dir_entry.path()).ok();
+				continue;
+			}
+			cache.insert(
+				filename,
+				metadata.len(),
+				metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+			);
+		}
+
+		cache
+	}
+
+	fn insert(&mut self, filename: String, size: u64, last_access: SystemTime) {
+		self.total_size += size;
+		if let Some(previous) =
+			self.entries.insert(filename, Entry { size, last_access })
+		{
+			self.total_size -= previous.size;
+		}
+	}
+
+	fn forget(&mut self, filename: &str) {
+		if let Some(entry) = self.entries.remove(filename) {
+			self.total_size -= entry.size;
+		}
+	}
+
+	fn remove(&mut self, filename: &str) {
+		self.forget(filename);
+		std::fs::remove_file(self.dir.join(filename)).ok();
+	}
+
+	/// Removes the least recently used files until under the max size
+	fn evict(&mut self) {
+		if self.total_size <= self.max_size {
+			return;
+		}
+
+		let mut by_access: Vec<(String, SystemTime)> = self
+			.entries
+			.iter()
+			.map(|(filename, entry)| (filename.clone(), entry.last_access))
+			.collect();
+		by_access.sort_unstable_by_key(|(_, last_access)| *last_access);
+
+		for (filename, _) in by_access {
+			if self.total_size <= self.max_size {
+				break;
+			}
+			log::debug!("Evicting {filename} from the image cache");
+			self.remove(&filename);
+		}
+	}
+}
+
+fn lock() -> MutexGuard<'static, DiskCache> {
+	CACHE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn get_path(filename: &str) -> PathBuf { lock().dir.join(filename) }
+
+/// Reads a cached file, marking it as recently used.
+pub fn read(filename: &str) -> Option<Vec<u8>> {
+	let path = get_path(filename);
+	let Ok(bytes) = std::fs::read(&path) else {
+		lock().forget(filename);
+		return None;
+	};
+
+	let now = SystemTime::now();
+	// So that the access time persists over restarts
+	if let Ok(file) = File::options().write(true).open(&path) {
+		file.set_modified(now).ok();
+	}
+	lock().insert(filename.to_owned(), bytes.len() as u64, now);
+
+	Some(bytes)
+}
+
+/// Writes a file to the cache, evicting others if needed.
+pub fn write(filename: &str, bytes: &[u8]) -> Result<(), String> {
+	crate::atomic_write(&get_path(filename), bytes)?;
+
+	let mut cache = lock();
+	cache.insert(filename.to_owned(), bytes.len() as u64, SystemTime::now());
+	cache.evict();
+	drop(cache);
+
+	Ok(())
+}
+
+/// Removes a file from the cache, such as a corrupted one.
+pub fn remove(filename: &str) { lock().remove(filename); }
+
+pub fn set_max_size(max_size: u64) {
+	let mut cache = lock();
+	cache.max_size = max_size;
+	cache.evict();
+}
+
+pub fn usage() -> Usage {
+	let cache = lock();
+	Usage { size: cache.total_size, files: cache.entries.len() }
+}
+
+/// Removes all the cached files.
+pub fn clear() {
+	let mut cache = lock();
+	let filenames: Vec<String> = cache.entries.keys().cloned().collect();
+	for filename in filenames {
+		cache.remove(&filename);
+	}
+	drop(cache);
+	log::info!("Cleared the image cache");
+}
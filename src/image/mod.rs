@@ -0,0 +1,345 @@
+use std::{fmt::Write, io::Cursor, time::Duration};
+
+use eframe::egui::{Color32, ColorImage};
+use image::{DynamicImage, ImageBuffer};
+use neos::AssetUrl;
+
+pub mod cache;
+mod webp_anim;
+
+/// Used for frames that don't specify a delay, same as what browsers do
+const DEFAULT_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// The frames of an image with how long each is shown, just one if it's not
+/// animated.
+pub type Frames = Vec<(DynamicImage, Duration)>;
+
+/// Limits the decoded frames of an animation, as those are kept in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct AnimationLimits {
+	pub max_frames: usize,
+	/// Of the decoded and downscaled RGBA pixels
+	pub max_bytes: u64,
+}
+
+/// How the image should be decoded
+#[derive(Debug, Clone, Copy)]
+pub struct DecodeOptions {
+	/// Downscales the image to fit in a square of this size
+	pub max_dimension: Option<u32>,
+	/// If `None`, only the first frame of animations is decoded
+	pub animation: Option<AnimationLimits>,
+}
+
+#[allow(clippy::module_name_repetitions)]
+// A lot taken from the egui example:
+// https://github.com/emilk/egui/blob/master/eframe/examples/image.rs
+pub fn from_dynamic_image(image: &DynamicImage) -> ColorImage {
+	let image_buffer = image.to_rgba8();
+	let size = [image.width() as usize, image.height() as usize];
+	let pixels = image_buffer.into_vec();
+
+	ColorImage::from_rgba_unmultiplied(size, &pixels)
+}
+
+/// A placeholder for images that failed to load, a red cross on gray.
+pub fn broken_image() -> ColorImage {
+	const SIZE: usize = 64;
+	const MARGIN: usize = 16;
+	const THICKNESS: usize = 3;
+
+	let pixels = (0..SIZE * SIZE)
+		.map(|i| {
+			let (x, y) = (i % SIZE, i / SIZE);
+			let inside = (MARGIN..SIZE - MARGIN).contains(&x)
+				&& (MARGIN..SIZE - MARGIN).contains(&y);
+			let on_cross =
+				x.abs_diff(y) < THICKNESS || (x + y).abs_diff(SIZE - 1) < THICKNESS;
+			if inside && on_cross {
+				Color32::from_rgb(200, 60, 60)
+			} else {
+				Color32::from_gray(60)
+			}
+		})
+		.collect();
+
+	ColorImage { size: [SIZE, SIZE], pixels }
+}
+
+/// This can block the whole thread for an API request, use with caution.
+pub fn retrieve(
+	url: &AssetUrl, options: DecodeOptions,
+) -> Result<Frames, String> {
+	let filename = url.filename();
+
+	if let Some(bytes) = cache::read(&filename) {
+		match verify_hash(url, &bytes).and_then(|()| decode(url, &bytes, options)) {
+			Ok(frames) => return Ok(frames),
+			// Possibly truncated by a crash or otherwise corrupted
+			Err(err) => {
+				log::warn!("Purging cached image {filename} - {err}");
+				cache::remove(&filename);
+			}
+		}
+	}
+
+	let bytes = fetch_asset(url)?;
+	let frames = decode(url, &bytes, options)?;
+
+	if let Err(err) = cache::write(&filename, &bytes) {
+		log::warn!("Failed to save asset {url:?} - {err}");
+	}
+
+	Ok(frames)
+}
+
+fn decode(
+	url: &AssetUrl, bytes: &[u8], options: DecodeOptions,
+) -> Result<Frames, String> {
+	use image::ImageFormat;
+
+	let format = image::guess_format(bytes).map_err(|err| {
+		format!("Failed to guess format of image {url:?} - {err}")
+	})?;
+
+	let mut frames = FrameCollector::new(options);
+
+	match format {
+		ImageFormat::Gif if options.animation.is_some() => {
+			decode_gif(bytes, &mut frames)
+				.map_err(|err| format!("Failed to decode gif image {url:?} - {err}"))?;
+		}
+		ImageFormat::WebP => decode_webp(bytes, &mut frames)
+			.map_err(|err| format!("Failed to decode webp image {url:?} - {err}"))?,
+		_ => {
+			let image = image::load_from_memory_with_format(bytes, format)
+				.map_err(|err| format!("Failed to decode image {url:?} - {err}"))?;
+			frames.push(image, Duration::ZERO);
+		}
+	}
+
+	if frames.frames.is_empty() {
+		return Err(format!("Image {url:?} has no frames"));
+	}
+
+	Ok(frames.frames)
+}
+
+fn decode_gif(bytes: &[u8], frames: &mut FrameCollector) -> Result<(), String> {
+	use image::{codecs::gif::GifDecoder, AnimationDecoder};
+
+	let decoder =
+		GifDecoder::new(Cursor::new(bytes)).map_err(|e| e.to_string())?;
+	for frame in decoder.into_frames() {
+		let frame = frame.map_err(|e| e.to_string())?;
+		let (numerator, denominator) = frame.delay().numer_denom_ms();
+		let delay =
+			Duration::from_millis(u64::from(numerator)) / denominator.max(1);
+		if !frames.push(DynamicImage::ImageRgba8(frame.into_buffer()), delay) {
+			break;
+		}
+	}
+
+	Ok(())
+}
+
+fn decode_webp(
+	bytes: &[u8], frames: &mut FrameCollector,
+) -> Result<(), String> {
+	let is_animated = webp::BitstreamFeatures::new(bytes)
+		.ok_or("Invalid bitstream")?
+		.has_animation();
+
+	if !is_animated {
+		let image = webp::Decoder::new(bytes).decode().ok_or("Decoding failed")?;
+		let image =
+			raw_to_image(image.width(), image.height(), &image, image.is_alpha())
+				.ok_or("Invalid image size")?;
+		frames.push(image, Duration::ZERO);
+		return Ok(());
+	}
+
+	// Decoded one frame at a time, so that no more are decoded than are kept
+	let mut decoder = webp_anim::AnimDecoder::new(bytes)?;
+	// The timestamps are of when the frames end
+	let mut previous_end = 0;
+	while let Some((image, end)) = decoder.next_frame()? {
+		let delay =
+			Duration::from_millis(u64::try_from(end - previous_end).unwrap_or(0));
+		previous_end = end;
+		if !frames.push(DynamicImage::ImageRgba8(image), delay) {
+			break;
+		}
+	}
+
+	Ok(())
+}
+
+fn raw_to_image(
+	width: u32, height: u32, pixels: &[u8], is_alpha: bool,
+) -> Option<DynamicImage> {
+	if is_alpha {
+		ImageBuffer::from_raw(width, height, pixels.to_vec())
+			.map(DynamicImage::ImageRgba8)
+	} else {
+		ImageBuffer::from_raw(width, height, pixels.to_vec())
+			.map(DynamicImage::ImageRgb8)
+	}
+}
+
+/// Downscales the decoded frames and stops taking them at the limits.
+struct FrameCollector {
+	options: DecodeOptions,
+	frames: Frames,
+	bytes: u64,
+}
+
+impl FrameCollector {
+	const fn new(options: DecodeOptions) -> Self {
+		Self { options, frames: Vec::new(), bytes: 0 }
+	}
+
+	/// Returns if more frames should be decoded.
+	fn push(&mut self, mut image: DynamicImage, delay: Duration) -> bool {
+		if let Some(max) = self.options.max_dimension {
+			if image.width() > max || image.height() > max {
+				image = image.thumbnail(max, max);
+			}
+		}
+
+		let Some(limits) = self.options.animation else {
+			self.frames.push((image, delay));
+			return false;
+		};
+
+		let bytes = u64::from(image.width()) * u64::from(image.height()) * 4;
+		// The first frame is always kept so that there's something to show
+		if !self.frames.is_empty()
+			&& (self.frames.len() >= limits.max_frames
+				|| self.bytes + bytes > limits.max_bytes)
+		{
+			log::debug!(
+				"Cut an animation short at {} frames to stay within the limits",
+				self.frames.len()
+			);
+			return false;
+		}
+
+		let delay =
+			if delay.as_millis() <= 10 { DEFAULT_FRAME_DELAY } else { delay };
+		self.frames.push((image, delay));
+		self.bytes += bytes;
+
+		true
+	}
+}
+
+/// Neos' assets are named after the SHA-256 hash of their contents, which
+/// other URLs can't be checked against.
+fn verify_hash(url: &AssetUrl, bytes: &[u8]) -> Result<(), String> {
+	let id = url.id();
+	if id.len() != 64 || !id.bytes().all(|b| b.is_ascii_hexdigit()) {
+		return Ok(());
+	}
+
+	let digest = ring::digest::digest(&ring::digest::SHA256, bytes);
+	let hash = digest.as_ref().iter().fold(String::new(), |mut hash, b| {
+		write!(hash, "{b:02x}").ok();
+		hash
+	});
+
+	if hash.eq_ignore_ascii_case(id) {
+		Ok(())
+	} else {
+		Err(format!("Hash mismatch of image {url:?}, got {hash}"))
+	}
+}
+
+fn fetch_asset(url: &AssetUrl) -> Result<Vec<u8>, String> {
+	let res = minreq::get(url.to_string())
+		.with_header("User-Agent", crate::USER_AGENT)
+		.send()
+		.map_err(|err| format!("Failed to send image request {url:?} - {err}"))?;
+
+	if res.status_code < 200 || res.status_code >= 300 {
+		return Err(format!(
+			"Image request status indicated failure {:?} - {}",
+			url, res.status_code,
+		));
+	}
+
+	let expected_len =
+		res.headers.get("content-length").and_then(|len| len.parse::<usize>().ok());
+	let data = res.into_bytes();
+
+	if let Some(expected_len) = expected_len {
+		if data.len() != expected_len {
+			return Err(format!(
+				"Image response was cut short {url:?} - got {} of {expected_len} bytes",
+				data.len()
+			));
+		}
+	}
+	verify_hash(url, &data)?;
+
+	Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// An animation of solid red, green and blue frames.
+	fn animated_webp() -> Vec<u8> {
+		let frames: Vec<Vec<u8>> =
+			[[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]
+				.iter()
+				.map(|color| color.repeat(4 * 4))
+				.collect();
+		let mut config = webp::WebPConfig::new().unwrap();
+		config.lossless = 1;
+		let mut encoder = webp::AnimEncoder::new(4, 4, &config);
+		for (pixels, start) in frames.iter().zip((0..).step_by(200)) {
+			encoder.add_frame(webp::AnimFrame::from_rgba(pixels, 4, 4, start));
+		}
+		encoder.try_encode().unwrap().to_vec()
+	}
+
+	fn options(animation: Option<AnimationLimits>) -> DecodeOptions {
+		DecodeOptions { max_dimension: None, animation }
+	}
+
+	#[test]
+	fn decodes_webp_animations() {
+		let mut frames = FrameCollector::new(options(Some(AnimationLimits {
+			max_frames: 10,
+			max_bytes: u64::MAX,
+		})));
+		decode_webp(&animated_webp(), &mut frames).unwrap();
+
+		assert_eq!(frames.frames.len(), 3);
+		let (first, delay) = &frames.frames[0];
+		assert_eq!(first.to_rgba8().get_pixel(0, 0).0, [255, 0, 0, 255]);
+		assert_eq!(*delay, Duration::from_millis(200));
+		assert_eq!(
+			frames.frames[2].0.to_rgba8().get_pixel(3, 3).0,
+			[0, 0, 255, 255]
+		);
+	}
+
+	#[test]
+	fn stops_decoding_webp_at_the_limits() {
+		let bytes = animated_webp();
+
+		let mut frames = FrameCollector::new(options(Some(AnimationLimits {
+			max_frames: 2,
+			max_bytes: u64::MAX,
+		})));
+		decode_webp(&bytes, &mut frames).unwrap();
+		assert_eq!(frames.frames.len(), 2);
+
+		let mut frames = FrameCollector::new(options(None));
+		decode_webp(&bytes, &mut frames).unwrap();
+		assert_eq!(frames.frames.len(), 1);
+	}
+}
@@ -0,0 +1,90 @@
+//! Decoding animated WebP images one frame at a time, as the `webp` crate
+//! decodes all of the frames at once.
+
+use std::{marker::PhantomData, ptr::NonNull};
+
+use image::RgbaImage;
+use libwebp_sys as sys;
+
+/// Frees the decoder when dropped.
+pub struct AnimDecoder<'a> {
+	decoder: NonNull<sys::WebPAnimDecoder>,
+	width: u32,
+	height: u32,
+	/// The decoder reads the bytes without copying them
+	bytes: PhantomData<&'a [u8]>,
+}
+
+impl<'a> AnimDecoder<'a> {
+	pub fn new(bytes: &'a [u8]) -> Result<Self, String> {
+		// SAFETY: The options and info are plain C structs that are initialized
+		// by libwebp, and the decoder is only used while the bytes are borrowed.
+		unsafe {
+			let mut options: sys::WebPAnimDecoderOptions = std::mem::zeroed();
+			if sys::WebPAnimDecoderOptionsInit(&raw mut options) == 0 {
+				return Err("Incompatible libwebp version".to_owned());
+			}
+			options.color_mode = sys::WEBP_CSP_MODE::MODE_RGBA;
+			// Already decoding in a background thread
+			options.use_threads = 0;
+
+			let data = sys::WebPData { bytes: bytes.as_ptr(), size: bytes.len() };
+			let decoder = NonNull::new(sys::WebPAnimDecoderNew(
+				&raw const data,
+				&raw const options,
+			))
+			.ok_or("Invalid animation")?;
+			let mut decoder =
+				Self { decoder, width: 0, height: 0, bytes: PhantomData };
+
+			let mut info: sys::WebPAnimInfo = std::mem::zeroed();
+			if sys::WebPAnimDecoderGetInfo(decoder.decoder.as_ptr(), &raw mut info)
+				== 0
+			{
+				return Err("Invalid animation info".to_owned());
+			}
+			decoder.width = info.canvas_width;
+			decoder.height = info.canvas_height;
+
+			Ok(decoder)
+		}
+	}
+
+	/// Decodes the next frame, returning it with the timestamp of when it ends
+	/// in milliseconds.
+	pub fn next_frame(&mut self) -> Result<Option<(RgbaImage, i32)>, String> {
+		let len = self.width as usize * self.height as usize * 4;
+
+		// SAFETY: On success the buffer has the RGBA pixels of the whole canvas,
+		// and stays valid until the next call to the decoder.
+		unsafe {
+			if sys::WebPAnimDecoderHasMoreFrames(self.decoder.as_ptr()) == 0 {
+				return Ok(None);
+			}
+
+			let mut buf = std::ptr::null_mut();
+			let mut timestamp = 0;
+			if sys::WebPAnimDecoderGetNext(
+				self.decoder.as_ptr(),
+				&raw mut buf,
+				&raw mut timestamp,
+			) == 0
+				|| buf.is_null()
+			{
+				return Err("Decoding a frame failed".to_owned());
+			}
+
+			let pixels = std::slice::from_raw_parts(buf, len).to_vec();
+			let image = RgbaImage::from_raw(self.width, self.height, pixels)
+				.ok_or("Invalid frame size")?;
+			Ok(Some((image, timestamp)))
+		}
+	}
+}
+
+impl Drop for AnimDecoder<'_> {
+	fn drop(&mut self) {
+		// SAFETY: The decoder was created by libwebp and isn't used after this
+		unsafe { sys::WebPAnimDecoderDelete(self.decoder.as_ptr()) };
+	}
+}
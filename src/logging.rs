@@ -0,0 +1,240 @@
+//! Logging to a size capped rotating file, and to memory for the logs page.
+
+use std::{
+	collections::VecDeque,
+	fs::File,
+	io::Write,
+	path::PathBuf,
+	sync::{LazyLock, Mutex, MutexGuard, PoisonError},
+};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use time::OffsetDateTime;
+
+/// Overrides the log levels, for example `info,neos_peeps::image=debug`
+const LOG_LEVEL_ENV: &str = "NEOS_PEEPS_LOG";
+/// After which the log file is rotated
+const MAX_FILE_SIZE: u64 = 1024 * 1024;
+/// How many of the previous log files are kept
+const ROTATED_FILES: usize = 3;
+/// How many entries are kept in memory for the logs page
+const MEMORY_LIMIT: usize = 5000;
+
+#[cfg(debug_assertions)]
+const DEFAULT_LEVELS: &str = "warn,neos_peeps=debug";
+#[cfg(not(debug_assertions))]
+const DEFAULT_LEVELS: &str = "warn,neos_peeps=info";
+
+static LOGGER: LazyLock<Logger> = LazyLock::new(|| {
+	let levels =
+		std::env::var(LOG_LEVEL_ENV).unwrap_or_else(|_| DEFAULT_LEVELS.to_owned());
+
+	Logger {
+		levels: Levels::parse(&levels),
+		file: Mutex::new(LogFile::open()),
+		entries: Mutex::default(),
+	}
+});
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+	pub time: OffsetDateTime,
+	pub level: Level,
+	pub target: String,
+	pub message: String,
+}
+
+/// What's shown in the logs page
+#[derive(Debug)]
+pub struct Filter {
+	pub level: LevelFilter,
+	pub target: String,
+	pub text: String,
+}
+
+impl Default for Filter {
+	fn default() -> Self {
+		Self {
+			level: LevelFilter::Info,
+			target: String::new(),
+			text: String::new(),
+		}
+	}
+}
+
+impl Filter {
+	pub fn matches(&self, entry: &Entry) -> bool {
+		entry.level <= self.level
+			&& entry.target.contains(&self.target)
+			&& entry.message.to_lowercase().contains(&self.text.to_lowercase())
+	}
+}
+
+/// The maximum levels per target, the longest matching target prefix wins.
+struct Levels {
+	default: LevelFilter,
+	targets: Vec<(String, LevelFilter)>,
+}
+
+impl Levels {
+	fn parse(spec: &str) -> Self {
+		let mut levels = Self { default: LevelFilter::Warn, targets: Vec::new() };
+
+		for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+			match directive.split_once('=') {
+				Some((target, level)) => {
+					if let Ok(level) = level.trim().parse() {
+						levels.targets.push((target.trim().to_owned(), level));
+					}
+				}
+				None => {
+					if let Ok(level) = directive.parse() {
+						levels.default = level;
+					}
+				}
+			}
+		}
+
+		levels.targets.sort_by_key(|(target, _)| std::cmp::Reverse(target.len()));
+		levels
+	}
+
+	fn level_for(&self, target: &str) -> LevelFilter {
+		self
+			.targets
+			.iter()
+			.find(|(prefix, _)| target.starts_with(prefix.as_str()))
+			.map_or(self.default, |(_, level)| *level)
+	}
+
+	fn max(&self) -> LevelFilter {
+		self
+			.targets
+			.iter()
+			.map(|(_, level)| *level)
+			.fold(self.default, std::cmp::Ord::max)
+	}
+}
+
+struct LogFile {
+	file: Option<File>,
+	size: u64,
+}
+
+impl LogFile {
+	fn open() -> Self {
+		let file =
+			std::fs::OpenOptions::new().create(true).append(true).open(path()).ok();
+		let size = file
+			.as_ref()
+			.and_then(|file| file.metadata().ok())
+			.map_or(0, |metadata| metadata.len());
+
+		Self { file, size }
+	}
+
+	fn write(&mut self, line: &str) {
+		if self.size + line.len() as u64 > MAX_FILE_SIZE {
+			self.rotate();
+		}
+
+		if let Some(file) = &mut self.file {
+			if file.write_all(line.as_bytes()).is_ok() {
+				self.size += line.len() as u64;
+			}
+		}
+	}
+
+	/// Moves the current file to `.1`, `.1` to `.2` and so on.
+	fn rotate(&mut self) {
+		self.file = None;
+		for i in (1..ROTATED_FILES).rev() {
+			std::fs::rename(rotated_path(i), rotated_path(i + 1)).ok();
+		}
+		std::fs::rename(path(), rotated_path(1)).ok();
+		*self = Self::open();
+	}
+}
+
+struct Logger {
+	levels: Levels,
+	file: Mutex<LogFile>,
+	entries: Mutex<VecDeque<Entry>>,
+}
+
+impl Log for Logger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		metadata.level() <= self.levels.level_for(metadata.target())
+	}
+
+	fn log(&self, record: &Record) {
+		if !self.enabled(record.metadata()) {
+			return;
+		}
+
+		let entry = Entry {
+			time: OffsetDateTime::now_utc(),
+			level: record.level(),
+			target: record.target().to_owned(),
+			message: record.args().to_string(),
+		};
+
+		let line = format!(
+			"{} {:<5} {}: {}\n",
+			entry
+				.time
+				.format(&time::format_description::well_known::Rfc3339)
+				.unwrap_or_default(),
+			entry.level,
+			entry.target,
+			entry.message
+		);
+		// There's no console on windows release builds
+		#[cfg(debug_assertions)]
+		eprint!("{line}");
+		lock(&self.file).write(&line);
+
+		let mut entries = lock(&self.entries);
+		entries.push_back(entry);
+		while entries.len() > MEMORY_LIMIT {
+			entries.pop_front();
+		}
+		drop(entries);
+	}
+
+	fn flush(&self) {
+		if let Some(file) = &mut lock(&self.file).file {
+			file.flush().ok();
+		}
+	}
+}
+
+/// Logging shouldn't stop working because some thread panicked.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+	mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub fn path() -> PathBuf {
+	crate::DATA_DIR.join(concat!(env!("CARGO_PKG_NAME"), ".log"))
+}
+
+fn rotated_path(i: usize) -> PathBuf {
+	crate::DATA_DIR.join(format!("{}.log.{i}", env!("CARGO_PKG_NAME")))
+}
+
+/// Sets up the logger, should be called once at startup.
+pub fn init() {
+	if log::set_logger(&*LOGGER).is_ok() {
+		log::set_max_level(LOGGER.levels.max());
+	}
+}
+
+/// The logged entries kept in memory that match the filter, newest first.
+pub fn filtered_entries(filter: &Filter) -> Vec<Entry> {
+	lock(&LOGGER.entries)
+		.iter()
+		.rev()
+		.filter(|entry| filter.matches(entry))
+		.cloned()
+		.collect()
+}
@@ -1,11 +1,12 @@
 //! The login page of the app
 
-use std::sync::Arc;
-
 use eframe::egui::Context;
-use neos::api_client::{AnyNeos, NeosUnauthenticated};
 
-use crate::app::NeosPeepsApp;
+use crate::{
+	app::NeosPeepsApp,
+	error::PeepsError,
+	notifications::Notification,
+};
 
 impl NeosPeepsApp {
 	/// Makes the current API try to use a session, or switch to unauthenticated
@@ -13,36 +14,53 @@ impl NeosPeepsApp {
 	pub fn try_use_session(
 		&mut self, user_session: neos::UserSession, ctx: &Context,
 	) {
-		let neos_api_arc = match &self.runtime.neos_api {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 		self.runtime.neos_api = None;
 		let auth_sender = self.threads.channels.auth_sender();
 		let user_session_sender = self.threads.channels.user_session_sender();
+		let notifications_sender = self.threads.channels.notifications_sender();
 		self.threads.spawn_login_op(move || {
-			let neos_api = NeosUnauthenticated::from((*neos_api_arc).clone())
-				.upgrade(user_session);
-
-			match neos_api.extend_session() {
-				Ok(_) => {
-					match auth_sender.send(Arc::new(neos_api.into())) {
-						Ok(_) => println!("Logged into Neos' API"),
-						Err(err) => eprintln!("Failed to send auth to main thread! {err}"),
-					};
+			let neos_api = neos_api.upgrade(user_session);
+
+			match neos_api.extend_session().map_err(PeepsError::from) {
+				Ok(()) => match auth_sender.send(neos_api) {
+					Ok(()) => {
+						notifications_sender
+							.send(Notification::info("Logged into Neos' API", ""))
+							.ok();
+					}
+					Err(err) => log::error!("Failed to send auth to main thread! {err}"),
+				},
+				// The session might still be fine, so it's kept for retrying later
+				Err(err @ PeepsError::Network(_)) => {
+					notifications_sender
+						.send(Notification::warning(
+							"Couldn't reach Neos' API, showing the last known data",
+							err.to_string(),
+						))
+						.ok();
+
+					if let Err(err) = auth_sender.send(neos_api.downgrade()) {
+						log::error!("Failed to send auth to main thread! {err}");
+					}
 				}
 				Err(err) => {
-					match auth_sender.send(Arc::new(neos_api.downgrade().into())) {
-						Ok(_) => {
-							println!("Error with Neos API user session extension: {err}");
-						}
-						Err(send_err) => eprintln!(
-							"Error with Neos API user session extension, and also to main thread failed! {err} - {send_err}"
-						),
-					};
+					notifications_sender
+						.send(Notification::warning(
+							"Couldn't continue the previous login, please log in again",
+							err.to_string(),
+						))
+						.ok();
+
+					if let Err(err) = auth_sender.send(neos_api.downgrade()) {
+						log::error!("Failed to send auth to main thread! {err}");
+					}
 
 					if let Err(err) = user_session_sender.send(None) {
-						eprintln!("Failed to send user_session to main thread! {err}");
+						log::error!("Failed to send user_session to main thread! {err}");
 					}
 				}
 			}
@@ -54,33 +72,44 @@ impl NeosPeepsApp {
 	pub fn login_new(
 		&mut self, session_request: neos::LoginCredentials, ctx: &Context,
 	) {
-		let neos_api_arc = match &self.runtime.neos_api {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 		self.runtime.neos_api = None;
 		let user_session_sender = self.threads.channels.user_session_sender();
 		let auth_sender = self.threads.channels.auth_sender();
+		let notifications_sender = self.threads.channels.notifications_sender();
 		self.threads.spawn_login_op(move || {
-			let neos_api: NeosUnauthenticated = ((*neos_api_arc).clone()).into();
+			let neos_api = neos_api.downgrade();
 
 			match neos_api.login(&session_request) {
 				Ok(neos_user_session) => {
-					match auth_sender
-						.send(Arc::new(neos_api.upgrade(neos_user_session.clone()).into()))
-					{
-						Ok(_) => println!("Logged into Neos' API"),
+					match auth_sender.send(neos_api.upgrade(neos_user_session.clone())) {
+						Ok(()) => {
+							notifications_sender
+								.send(Notification::info("Logged into Neos' API", ""))
+								.ok();
+						}
 						Err(err) => {
-							eprintln!("Failed to send auth to main thread! {err}");
+							log::error!("Failed to send auth to main thread! {err}");
 						}
-					};
+					}
 
 					if let Err(err) = user_session_sender.send(Some(neos_user_session)) {
-						eprintln!("Failed to send user_session to main thread! {err}");
+						log::error!("Failed to send user_session to main thread! {err}");
 					}
 				}
 				Err(err) => {
-					eprintln!("Error with Neos API login request: {err}");
+					notifications_sender
+						.send(Notification::error(
+							"Failed to log in",
+							PeepsError::from(err).to_string(),
+						))
+						.ok();
+					if let Err(err) = auth_sender.send(neos_api) {
+						log::error!("Failed to send auth to main thread! {err}");
+					}
 				}
 			}
 		});
@@ -89,7 +118,7 @@ impl NeosPeepsApp {
 	}
 
 	pub fn logout(&mut self, ctx: &Context) {
-		let neos_api_arc = match &mut self.runtime.neos_api {
+		let neos_api = match &mut self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
@@ -97,20 +126,14 @@ impl NeosPeepsApp {
 		let user_session_sender = self.threads.channels.user_session_sender();
 		let auth_sender = self.threads.channels.auth_sender();
 		self.threads.spawn_login_op(move || {
-			let new_api = match (*neos_api_arc).clone() {
-				AnyNeos::Authenticated(neos_api) => {
-					neos_api.logout().ok();
-					neos_api.downgrade()
-				}
-				AnyNeos::Unauthenticated(neos_api) => neos_api,
-			};
+			neos_api.logout().ok();
 
-			if let Err(err) = auth_sender.send(Arc::new(new_api.into())) {
-				eprintln!("Failed to send auth to main thread! {err}");
+			if let Err(err) = auth_sender.send(neos_api.downgrade()) {
+				log::error!("Failed to send auth to main thread! {err}");
 			}
 
 			if let Err(err) = user_session_sender.send(None) {
-				eprintln!("Failed to send user_session to main thread! {err}");
+				log::error!("Failed to send user_session to main thread! {err}");
 			}
 		});
 
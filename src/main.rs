@@ -6,7 +6,13 @@
 // Not much can be done about it :/
 #![allow(clippy::multiple_crate_versions)]
 
-use std::path::PathBuf;
+use std::{
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		LazyLock,
+	},
+};
 
 const LICENSE_TEXT: &str = include_str!("../LICENSE.md");
 
@@ -19,26 +25,74 @@ const USER_AGENT: &str = concat!(
 	")"
 );
 
-lazy_static::lazy_static! {
-	static ref TEMP_DIR: PathBuf = {
-		let dir = std::env::temp_dir().join(env!("CARGO_PKG_NAME"));
-		std::fs::create_dir_all(&dir).unwrap();
-		dir.canonicalize().unwrap()
-	};
+/// The image cache, which evicts the files directly in it.
+static TEMP_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+	let dir = std::env::temp_dir().join(env!("CARGO_PKG_NAME"));
+	// Failing to create it is noticed later, when files can't be written
+	std::fs::create_dir_all(&dir).ok();
+	dir.canonicalize().unwrap_or(dir)
+});
+
+/// Where the app's other data is persisted, next to eframe's storage.
+static DATA_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+	directories_next::ProjectDirs::from("", "", env!("CARGO_PKG_NAME"))
+		.map(|dirs| dirs.data_dir().to_owned())
+		.filter(|dir| std::fs::create_dir_all(dir).is_ok())
+		.unwrap_or_else(|| {
+			// A subdirectory so that the image cache doesn't evict the data
+			let dir = TEMP_DIR.join("data");
+			std::fs::create_dir_all(&dir).ok();
+			dir
+		})
+});
+
+/// Makes the temporary file names unique, in case of concurrent writes
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+/// Of the files being written by [`atomic_write`]
+const TMP_EXT: &str = "tmp";
+
+/// Writes the file under a temporary name first and then renames it, so that
+/// a failed write or a crash can't leave a truncated file in its place.
+fn atomic_write(path: &Path, bytes: &[u8]) -> Result<(), String> {
+	let mut tmp_name = path.file_name().ok_or("Not a file path")?.to_owned();
+	tmp_name.push(format!(
+		".{}.{TMP_EXT}",
+		TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+	));
+	let tmp_path = path.with_file_name(tmp_name);
+
+	std::fs::write(&tmp_path, bytes)
+		.and_then(|()| std::fs::rename(&tmp_path, path))
+		.map_err(|e| {
+			std::fs::remove_file(&tmp_path).ok();
+			e.to_string()
+		})
 }
 
 mod app;
+mod backend;
 mod data;
+mod error;
+mod export;
+mod history;
 mod image;
+mod logging;
 mod login;
 mod messages;
+mod notifications;
+mod outbox;
+mod realtime;
+mod rich_text;
 mod sessions;
+mod snapshot;
 mod styling;
 mod threading;
 mod updating;
 mod users;
 
 fn main() {
+	logging::init();
+
 	let native_options = eframe::NativeOptions::default();
 	let app_creator: eframe::AppCreator =
 		Box::new(|creation_ctx| Box::new(app::NeosPeepsApp::new(creation_ctx)));
@@ -1,21 +1,30 @@
 //! The friends page of the app
 
-use std::{borrow::Borrow, cmp::Ordering, collections::HashMap, sync::Arc};
+use std::{cmp::Ordering, collections::HashMap};
 
 use ahash::RandomState;
 use crossbeam::channel::Sender;
 use eframe::egui::Context;
-use neos::api_client::AnyNeos;
 use time::OffsetDateTime;
 
-use crate::app::NeosPeepsApp;
+use crate::{
+	app::NeosPeepsApp,
+	backend::NeosBackend,
+	data::OlderMessages,
+	error::PeepsError,
+	notifications::Notification,
+	threading::Ticket,
+};
+
+/// The most messages that the API returns at once
+pub const MESSAGES_PAGE: u16 = 100;
 
 #[allow(clippy::module_name_repetitions)]
 pub type UserMessages = sorted_vec::SortedSet<Message>;
 #[allow(clippy::module_name_repetitions)]
 pub type AllMessages = HashMap<neos::id::User, UserMessages, RandomState>;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Message(pub neos::Message);
 
 impl PartialEq for Message {
@@ -35,96 +44,417 @@ impl PartialOrd for Message {
 	}
 }
 
+/// The kinds of [`neos::MessageContents`], for filtering messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentKind {
+	Text,
+	SessionInvite,
+	Sound,
+	Object,
+	CreditTransfer,
+	SugarCubes,
+}
+
+impl ContentKind {
+	pub const ALL: [Self; 6] = [
+		Self::Text,
+		Self::SessionInvite,
+		Self::Sound,
+		Self::Object,
+		Self::CreditTransfer,
+		Self::SugarCubes,
+	];
+
+	pub const fn of(contents: &neos::MessageContents) -> Self {
+		match contents {
+			neos::MessageContents::Text(_) => Self::Text,
+			neos::MessageContents::SessionInvite(_) => Self::SessionInvite,
+			neos::MessageContents::Sound(_) => Self::Sound,
+			neos::MessageContents::Object(_) => Self::Object,
+			neos::MessageContents::CreditTransfer(_) => Self::CreditTransfer,
+			neos::MessageContents::SugarCubes(_) => Self::SugarCubes,
+		}
+	}
+
+	pub const fn name(self) -> &'static str {
+		match self {
+			Self::Text => "Text",
+			Self::SessionInvite => "Session invites",
+			Self::Sound => "Audio messages",
+			Self::Object => "Records",
+			Self::CreditTransfer => "Credit transfers",
+			Self::SugarCubes => "Kofi tips",
+		}
+	}
+}
+
+/// A plain text version of the contents, with the links of the assets.
+pub fn contents_text(contents: &neos::MessageContents) -> String {
+	match contents {
+		neos::MessageContents::Text(text) => text.clone(),
+		neos::MessageContents::SessionInvite(session) => format!(
+			"Invited to session: {} ({})",
+			session.stripped_name(),
+			session.id.as_ref()
+		),
+		neos::MessageContents::Sound(record) => {
+			format!("Audio message: {}", record.asset_uri.to_string())
+		}
+		neos::MessageContents::Object(record) => format!(
+			"Record: {} - {} {}",
+			record.name,
+			record.description,
+			record.asset_uri.to_string()
+		),
+		neos::MessageContents::CreditTransfer(transaction) => format!(
+			"Sent {} {}: {}",
+			transaction.amount, transaction.token, transaction.comment
+		),
+		neos::MessageContents::SugarCubes(_) => {
+			"Kofi tipping transaction".to_owned()
+		}
+	}
+}
+
+/// What's shown in the message search page
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchFilter {
+	pub text: String,
+	/// Part of the sender's username or ID
+	pub sender: String,
+	/// Dates as `YYYY-MM-DD`, both ends inclusive
+	pub from: String,
+	pub to: String,
+	pub kind: Option<ContentKind>,
+}
+
+impl SearchFilter {
+	pub const fn is_empty(&self) -> bool {
+		self.text.is_empty()
+			&& self.sender.is_empty()
+			&& self.from.is_empty()
+			&& self.to.is_empty()
+			&& self.kind.is_none()
+	}
+
+	/// Parses a date of the range, `None` if it's empty or invalid
+	pub fn parse_date(date: &str) -> Option<time::Date> {
+		const FORMAT: &[time::format_description::FormatItem<'static>] =
+			time::macros::format_description!("[year]-[month]-[day]");
+
+		time::Date::parse(date.trim(), FORMAT).ok()
+	}
+
+	/// Prepares the filter for matching many messages.
+	pub fn matcher(&self) -> SearchMatcher {
+		SearchMatcher {
+			text: self.text.to_lowercase(),
+			sender: self.sender.to_lowercase(),
+			kind: self.kind,
+			from: Self::parse_date(&self.from),
+			to: Self::parse_date(&self.to),
+		}
+	}
+}
+
+/// A [`SearchFilter`] with its text lowercased and dates parsed
+pub struct SearchMatcher {
+	text: String,
+	sender: String,
+	kind: Option<ContentKind>,
+	from: Option<time::Date>,
+	to: Option<time::Date>,
+}
+
+impl SearchMatcher {
+	pub fn matches(&self, message: &neos::Message, sender_name: &str) -> bool {
+		let date = message.send_time.date();
+
+		self.kind.is_none_or(|kind| kind == ContentKind::of(&message.content))
+			&& self.from.is_none_or(|from| date >= from)
+			&& self.to.is_none_or(|to| date <= to)
+			&& (sender_name.to_lowercase().contains(&self.sender)
+				|| message.sender_id.as_ref().to_lowercase().contains(&self.sender))
+			&& contents_text(&message.content).to_lowercase().contains(&self.text)
+	}
+}
+
+/// The results of a search, kept until the filter or the data changes
+#[derive(Debug)]
+pub struct SearchHits {
+	pub filter: SearchFilter,
+	/// Of the data that was searched
+	pub revision: u64,
+	/// The conversation, the index of the message in it and the sender's
+	/// name, newest first
+	pub hits: Vec<(neos::id::User, usize, String)>,
+}
+
+/// If the message was received and not read yet
+fn is_unread(message: &neos::Message) -> bool {
+	message.read_time.is_none() && message.sender_id != message.owner_id
+}
+
 impl NeosPeepsApp {
 	/// Refreshes messages in a background thread
-	pub fn refresh_messages(&mut self, ctx: &Context) {
-		let neos_api_arc = match &self.runtime.neos_api {
+	pub fn refresh_messages(&self, ctx: &Context) {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 
-		self.threads.loading.messages.set(true);
-		let messages_sender = self.threads.channels.messages_sender();
+		let ticket = self.threads.loading.messages.start();
+		let messages_sender = self.threads.channels.refreshed_messages_sender();
 		self.threads.spawn_data_op(move || {
-			Self::get_messages(neos_api_arc, messages_sender, 100, true, None, None);
+			Self::get_messages(
+				&*neos_api,
+				ticket,
+				&messages_sender,
+				MESSAGES_PAGE,
+				true,
+				None,
+				None,
+			);
 		});
 
 		ctx.request_repaint();
 	}
 
 	pub fn fetch_user_chat(
-		&mut self, ctx: &Context, user: neos::id::User,
+		&self, ctx: &Context, user: neos::id::User,
 		from_time: Option<OffsetDateTime>,
 	) {
-		let neos_api_arc = match &self.runtime.neos_api {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 
-		self.threads.loading.messages.set(true);
+		let ticket = self.threads.loading.messages.start();
 		let messages_sender = self.threads.channels.messages_sender();
 		self.threads.spawn_data_op(move || {
 			Self::get_messages(
-				neos_api_arc,
-				messages_sender,
-				100,
+				&*neos_api,
+				ticket,
+				&messages_sender,
+				MESSAGES_PAGE,
 				false,
 				from_time,
-				Some(user),
+				Some(&user),
 			);
 		});
 
 		ctx.request_repaint();
 	}
 
-	#[allow(clippy::needless_pass_by_value)]
+	/// Fetches a page of the messages before the time in a background thread
+	pub fn fetch_older_messages(
+		&self, ctx: &Context, user: neos::id::User, before: OffsetDateTime,
+	) {
+		let neos_api = match &self.runtime.neos_api {
+			Some(api) => api.clone(),
+			None => return,
+		};
+
+		self
+			.runtime
+			.older_messages
+			.borrow_mut()
+			.insert(user.clone(), OlderMessages::Loading);
+		let older_messages_sender = self.threads.channels.older_messages_sender();
+		self.threads.spawn_data_op(move || {
+			let res = neos_api
+				.get_messages(MESSAGES_PAGE, false, Some(before), Some(&user))
+				.map_err(PeepsError::from);
+			older_messages_sender.send((user, res)).ok();
+		});
+
+		ctx.request_repaint();
+	}
+
+	/// Marks the unread messages from the user as read in a background thread
+	pub fn mark_read(&self, ctx: &Context, user_id: &neos::id::User) {
+		let Some(neos_api) = &self.runtime.neos_api else {
+			return;
+		};
+		let Some(user_session) = &self.stored.user_session else {
+			return;
+		};
+		let Some(messages) = self.runtime.messages.get(user_id) else {
+			return;
+		};
+		if !self.runtime.marking_read.borrow_mut().insert(user_id.clone()) {
+			return;
+		}
+
+		let ids: Vec<String> = messages
+			.iter()
+			.filter(|message| is_unread(&message.0))
+			.map(|message| message.0.id.clone())
+			.collect();
+		let neos_api = neos_api.clone();
+		let owner_id = user_session.user_id.clone();
+		let user_id = user_id.clone();
+		let marked_read_sender = self.threads.channels.marked_read_sender();
+		self.threads.spawn_data_op(move || {
+			let res = neos_api
+				.mark_messages_read(&owner_id, &ids)
+				.map(|()| ids)
+				.map_err(PeepsError::from);
+			marked_read_sender.send((user_id, res)).ok();
+		});
+
+		ctx.request_repaint();
+	}
+
+	/// Updates the messages that were marked as read without refetching them
+	pub fn set_read(&mut self, user_id: &neos::id::User, ids: &[String]) {
+		let Some(messages) = self.runtime.messages.get_mut(user_id) else {
+			return;
+		};
+
+		let now = OffsetDateTime::now_utc();
+		let read: Vec<Message> = messages
+			.iter()
+			.filter(|message| ids.contains(&message.0.id))
+			.map(|message| {
+				let mut message = message.0.clone();
+				message.read_time = Some(now);
+				Message(message)
+			})
+			.collect();
+		for message in &read {
+			messages.replace(message.clone());
+		}
+
+		// So that the messages aren't unread again after a restart
+		let mut read_messages = AllMessages::default();
+		read_messages.insert(user_id.clone(), UserMessages::from_unsorted(read));
+		self.store_messages(&read_messages);
+		self.save_snapshot();
+
+		self.update_unread_counts();
+	}
+
+	/// Counts the unread messages of each conversation again.
+	pub fn update_unread_counts(&mut self) {
+		self.runtime.unread = self
+			.runtime
+			.messages
+			.iter()
+			.map(|(user_id, messages)| {
+				let unread = messages.iter().filter(|m| is_unread(&m.0)).count();
+				(user_id.clone(), unread)
+			})
+			.filter(|(_, unread)| *unread > 0)
+			.collect();
+	}
+
+	pub fn unread_count(&self, user_id: &neos::id::User) -> usize {
+		self.runtime.unread.get(user_id).copied().unwrap_or(0)
+	}
+
+	pub fn total_unread(&self) -> usize { self.runtime.unread.values().sum() }
+
 	fn get_messages(
-		neos_api_arc: Arc<AnyNeos>,
-		messages_sender: Sender<Result<AllMessages, String>>, max_amount: u16,
-		unread_only: bool, from_time: impl Borrow<Option<OffsetDateTime>>,
-		user: impl Borrow<Option<neos::id::User>>,
+		neos_api: &dyn NeosBackend, ticket: Ticket,
+		messages_sender: &Sender<(Ticket, Result<AllMessages, PeepsError>)>,
+		max_amount: u16, unread_only: bool, from_time: Option<OffsetDateTime>,
+		user: Option<&neos::id::User>,
 	) {
-		if let AnyNeos::Authenticated(neos_api) = &*neos_api_arc {
-			match neos_api.get_messages(max_amount, unread_only, from_time, user) {
-				Ok(messages) => {
-					messages_sender.send(Ok(Self::split_by_user(messages))).unwrap();
-				}
-				Err(e) => {
-					messages_sender.send(Err(e.to_string())).unwrap();
-				}
+		match neos_api.get_messages(max_amount, unread_only, from_time, user) {
+			Ok(messages) => {
+				messages_sender
+					.send((ticket, Ok(Self::split_by_user(messages))))
+					.unwrap();
+			}
+			Err(e) => {
+				messages_sender.send((ticket, Err(e.into()))).unwrap();
 			}
 		}
 	}
 
-	pub fn send_message(&mut self, ctx: &Context, message: neos::Message) {
-		let neos_api_arc = match &self.runtime.neos_api {
-			Some(api) => api.clone(),
-			None => return,
+	/// Queues a message inviting the user to the session.
+	pub fn send_invite(
+		&self, ctx: &Context, recipient: neos::id::User,
+		session: &neos::SessionInfo,
+	) {
+		let Some(user_session) = &self.stored.user_session else {
+			return;
 		};
+		let message = neos::Message::new(
+			neos::MessageContents::SessionInvite(Box::new(session.clone())),
+			user_session.user_id.clone(),
+			recipient,
+		);
+		self.queue_message(ctx, message);
+	}
 
-		self.threads.loading.messages.set(true);
+	pub fn send_message(&self, ctx: &Context, message: neos::Message) {
+		let Some(neos_api) = self.runtime.neos_api.clone() else {
+			// Such as while logging in again, otherwise it'd be sending forever
+			self.fail_outgoing(&message.id, "Not connected".to_owned());
+			return;
+		};
+
+		let ticket = self.threads.loading.messages.start();
 		let messages_sender = self.threads.channels.messages_sender();
+		let notifications_sender = self.threads.channels.notifications_sender();
+		let sent_sender = self.threads.channels.sent_sender();
 		self.threads.spawn_data_op(move || {
-			if let AnyNeos::Authenticated(neos_api) = &*neos_api_arc {
-				let to_id = message.recipient_id.clone();
-				if let Err(e) = neos_api.send_message(message) {
-					eprintln!("Error sending message! {e:?}");
-				}
-				Self::get_messages(
-					neos_api_arc,
-					messages_sender,
-					32,
-					false,
-					None,
-					Some(to_id),
-				);
+			let res = neos_api.send_message(&message).map_err(PeepsError::from);
+			if let Err(e) = &res {
+				notifications_sender
+					.send(Notification::error("Failed to send message", e.to_string()))
+					.ok();
 			}
+			sent_sender.send((message.id.clone(), res)).ok();
+			Self::get_messages(
+				&*neos_api,
+				ticket,
+				&messages_sender,
+				32,
+				false,
+				None,
+				Some(&message.recipient_id),
+			);
 		});
 
 		ctx.request_repaint();
 	}
 
-	fn split_by_user(messages: Vec<neos::Message>) -> AllMessages {
+	/// Adds new messages and replaces the existing ones with the same ID.
+	/// Returns if any of the messages changed.
+	pub fn merge_messages(&mut self, messages: AllMessages) -> bool {
+		let retention = self.stored.message_retention;
+		let mut changed = false;
+		for (user_id, fetched_messages) in messages {
+			let stored_messages = self.runtime.messages.entry(user_id).or_default();
+			let len = stored_messages.len();
+			for message in fetched_messages.into_vec() {
+				if let Ok(i) = stored_messages.binary_search(&message) {
+					// Older copies, such as from the disk, don't replace newer ones
+					if stored_messages[i].0.last_update_time > message.0.last_update_time
+						|| stored_messages[i].0 == message.0
+					{
+						continue;
+					}
+				}
+				stored_messages.replace(message);
+				changed = true;
+			}
+			retention.apply_to_messages(stored_messages);
+			changed |= stored_messages.len() != len;
+		}
+
+		if changed {
+			self.runtime.data_revision += 1;
+		}
+		self.update_unread_counts();
+		self.confirm_outgoing();
+		changed
+	}
+
+	pub fn split_by_user(messages: Vec<neos::Message>) -> AllMessages {
 		let hash_builder = RandomState::new();
 		let mut sorted_messages: AllMessages = HashMap::with_hasher(hash_builder);
 
@@ -144,3 +474,70 @@ impl NeosPeepsApp {
 		sorted_messages
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn message(text: &str) -> neos::Message {
+		serde_json::from_value(serde_json::json!({
+			"id": "MSG-test",
+			"ownerId": "U-test",
+			"senderId": "U-friend",
+			"recipientId": "U-test",
+			"messageType": "Text",
+			"content": text,
+			"sendTime": "2023-05-10T12:00:00Z",
+			"lastUpdateTime": "2023-05-10T12:00:00Z"
+		}))
+		.unwrap()
+	}
+
+	#[test]
+	fn search_ignores_case() {
+		let filter = SearchFilter {
+			text: "HELLO".to_owned(),
+			sender: "fri".to_owned(),
+			..SearchFilter::default()
+		};
+		let matcher = filter.matcher();
+		assert!(matcher.matches(&message("Well hello there"), "Friend"));
+		assert!(!matcher.matches(&message("Goodbye"), "Friend"));
+		// Or by the sender's ID
+		assert!(matcher.matches(&message("Hello"), "Someone"));
+
+		let filter = SearchFilter { sender: "bob".to_owned(), ..filter };
+		assert!(!filter.matcher().matches(&message("Hello"), "Friend"));
+	}
+
+	#[test]
+	fn search_dates_are_inclusive() {
+		let filter = |from: &str, to: &str| SearchFilter {
+			from: from.to_owned(),
+			to: to.to_owned(),
+			..SearchFilter::default()
+		};
+		let message = message("Hello");
+		assert!(filter("2023-05-10", "2023-05-10").matcher().matches(&message, ""));
+		assert!(!filter("2023-05-11", "").matcher().matches(&message, ""));
+		assert!(!filter("", "2023-05-09").matcher().matches(&message, ""));
+		// Invalid dates don't limit the search
+		assert!(filter("2023-13-40", "").matcher().matches(&message, ""));
+	}
+
+	#[test]
+	fn refetching_the_same_messages_changes_nothing() {
+		let mut app = NeosPeepsApp::default();
+		let messages = || NeosPeepsApp::split_by_user(vec![message("Hello")]);
+		assert!(app.merge_messages(messages()));
+		let revision = app.runtime.data_revision;
+
+		assert!(!app.merge_messages(messages()));
+		assert_eq!(app.runtime.data_revision, revision);
+
+		let mut edited = message("Hello again");
+		edited.last_update_time += time::Duration::minutes(1);
+		assert!(app.merge_messages(NeosPeepsApp::split_by_user(vec![edited])));
+		assert!(app.runtime.data_revision > revision);
+	}
+}
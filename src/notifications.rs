@@ -0,0 +1,152 @@
+//! Notifications about things happening in the background, mostly errors.
+
+use std::{collections::VecDeque, time::Duration};
+
+use eframe::egui::Color32;
+use time::OffsetDateTime;
+
+/// How many notifications to keep in the history
+const HISTORY_LIMIT: usize = 200;
+/// How long a notification is shown as a toast
+pub const TOAST_DURATION: Duration = Duration::from_secs(6);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+	Info,
+	Warning,
+	Error,
+}
+
+impl Severity {
+	pub const fn color(self) -> Color32 {
+		match self {
+			Self::Info => Color32::LIGHT_BLUE,
+			Self::Warning => Color32::YELLOW,
+			Self::Error => Color32::RED,
+		}
+	}
+
+	pub const fn level(self) -> log::Level {
+		match self {
+			Self::Info => log::Level::Info,
+			Self::Warning => log::Level::Warn,
+			Self::Error => log::Level::Error,
+		}
+	}
+}
+
+impl AsRef<str> for Severity {
+	fn as_ref(&self) -> &str {
+		match self {
+			Self::Info => "Info",
+			Self::Warning => "Warning",
+			Self::Error => "Error",
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct Notification {
+	pub severity: Severity,
+	/// A short human readable description
+	pub summary: String,
+	/// The details of the error for example
+	pub details: String,
+	/// When the notification was last received
+	pub time: OffsetDateTime,
+	/// How many times the same notification has been received in a row
+	pub count: u32,
+}
+
+impl Notification {
+	pub fn new(
+		severity: Severity, summary: impl Into<String>, details: impl Into<String>,
+	) -> Self {
+		Self {
+			severity,
+			summary: summary.into(),
+			details: details.into(),
+			time: OffsetDateTime::now_utc(),
+			count: 1,
+		}
+	}
+
+	pub fn info(summary: impl Into<String>, details: impl Into<String>) -> Self {
+		Self::new(Severity::Info, summary, details)
+	}
+
+	pub fn warning(
+		summary: impl Into<String>, details: impl Into<String>,
+	) -> Self {
+		Self::new(Severity::Warning, summary, details)
+	}
+
+	pub fn error(summary: impl Into<String>, details: impl Into<String>) -> Self {
+		Self::new(Severity::Error, summary, details)
+	}
+
+	/// If the notification should still be shown as a toast
+	pub fn is_recent(&self) -> bool {
+		OffsetDateTime::now_utc() - self.time < TOAST_DURATION
+	}
+
+	/// The text for copying the notification into a bug report for example
+	pub fn copy_text(&self) -> String {
+		format!(
+			"[{}] {} {}\n{}",
+			self.severity.as_ref(),
+			self.time,
+			self.summary,
+			self.details
+		)
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct Notifications {
+	/// Oldest first
+	history: VecDeque<Notification>,
+	/// Amount of notifications since the history was last viewed
+	pub unseen: usize,
+}
+
+impl Notifications {
+	/// Adds a notification, merging it with the latest one if it's identical.
+	pub fn push(&mut self, notification: Notification) {
+		log::log!(
+			notification.severity.level(),
+			"{} {}",
+			notification.summary,
+			notification.details
+		);
+
+		if let Some(latest) = self.history.back_mut() {
+			if latest.severity == notification.severity
+				&& latest.summary == notification.summary
+				&& latest.details == notification.details
+			{
+				latest.count += 1;
+				latest.time = notification.time;
+				return;
+			}
+		}
+
+		self.unseen += 1;
+		self.history.push_back(notification);
+		while self.history.len() > HISTORY_LIMIT {
+			self.history.pop_front();
+		}
+	}
+
+	/// Newest first
+	pub fn iter(&self) -> impl Iterator<Item = &Notification> {
+		self.history.iter().rev()
+	}
+
+	pub fn is_empty(&self) -> bool { self.history.is_empty() }
+
+	pub fn clear(&mut self) {
+		self.history.clear();
+		self.unseen = 0;
+	}
+}
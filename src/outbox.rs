@@ -0,0 +1,144 @@
+//! The sent messages that haven't been confirmed by a refetch yet, kept so
+//! that a failed send doesn't lose the message.
+
+use eframe::egui::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{app::NeosPeepsApp, error::PeepsError};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OutgoingState {
+	/// Sent or being sent, until it shows up in a refetch
+	Sending,
+	Failed(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OutgoingMessage {
+	pub message: neos::Message,
+	pub state: OutgoingState,
+}
+
+impl NeosPeepsApp {
+	/// Adds the message to the outbox and sends it.
+	pub fn queue_message(&self, ctx: &Context, message: neos::Message) {
+		self.stored.outbox.borrow_mut().push(OutgoingMessage {
+			message: message.clone(),
+			state: OutgoingState::Sending,
+		});
+		self.send_message(ctx, message);
+	}
+
+	/// Sends a message from the outbox again.
+	pub fn retry_message(&self, ctx: &Context, id: &str) {
+		let message = self
+			.stored
+			.outbox
+			.borrow_mut()
+			.iter_mut()
+			.find(|outgoing| outgoing.message.id == id)
+			.map(|outgoing| {
+				outgoing.state = OutgoingState::Sending;
+				outgoing.message.clone()
+			});
+
+		if let Some(message) = message {
+			self.send_message(ctx, message);
+		}
+	}
+
+	/// Removes a message from the outbox without sending it.
+	pub fn delete_outgoing(&self, id: &str) {
+		self
+			.stored
+			.outbox
+			.borrow_mut()
+			.retain(|outgoing| outgoing.message.id != id);
+	}
+
+	/// The messages to the user that haven't been confirmed yet
+	pub fn outgoing_to(&self, user_id: &neos::id::User) -> Vec<OutgoingMessage> {
+		self
+			.stored
+			.outbox
+			.borrow()
+			.iter()
+			.filter(|outgoing| &outgoing.message.recipient_id == user_id)
+			.cloned()
+			.collect()
+	}
+
+	/// Marks a message as failed if sending it didn't work, otherwise it's
+	/// waited to show up in a refetch.
+	pub fn message_sent(&self, id: &str, res: Result<(), PeepsError>) {
+		if let Err(err) = res {
+			self.fail_outgoing(id, err.to_string());
+		}
+	}
+
+	/// Lets the message be retried or deleted.
+	pub fn fail_outgoing(&self, id: &str, reason: String) {
+		if let Some(outgoing) = self
+			.stored
+			.outbox
+			.borrow_mut()
+			.iter_mut()
+			.find(|outgoing| outgoing.message.id == id)
+		{
+			outgoing.state = OutgoingState::Failed(reason);
+		}
+	}
+
+	/// Removes the messages that have been fetched from the outbox.
+	pub fn confirm_outgoing(&mut self) {
+		let messages = &self.runtime.messages;
+		self.stored.outbox.get_mut().retain(|outgoing| {
+			let confirmed =
+				messages.get(&outgoing.message.recipient_id).is_some_and(|fetched| {
+					fetched.iter().rev().any(|m| m.0.id == outgoing.message.id)
+				});
+			!confirmed
+		});
+	}
+
+	/// The messages that were being sent when the app was closed may not have
+	/// been, so those can be retried.
+	pub fn interrupt_outgoing(&mut self) {
+		for outgoing in self.stored.outbox.get_mut() {
+			if matches!(outgoing.state, OutgoingState::Sending) {
+				outgoing.state =
+					OutgoingState::Failed("Interrupted by closing the app".to_owned());
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sending_without_connection_fails() {
+		let mut app = NeosPeepsApp::default();
+		// Such as while logging in
+		app.runtime.neos_api = None;
+		let message: neos::Message = serde_json::from_value(serde_json::json!({
+			"id": "MSG-test",
+			"ownerId": "U-test",
+			"senderId": "U-test",
+			"recipientId": "U-friend",
+			"messageType": "Text",
+			"content": "Hello",
+			"sendTime": "2023-05-10T12:00:00Z",
+			"lastUpdateTime": "2023-05-10T12:00:00Z"
+		}))
+		.unwrap();
+		app.queue_message(&Context::default(), message);
+
+		let outgoing =
+			app.outgoing_to(&neos::id::User::try_from("U-friend").unwrap());
+		assert!(
+			matches!(&outgoing[0].state, OutgoingState::Failed(reason) if reason == "Not connected")
+		);
+	}
+}
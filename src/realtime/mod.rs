@@ -0,0 +1,348 @@
+//! Optional realtime updates from Neos' `SignalR` hub, with the background
+//! polling as a fallback for when the connection is down.
+
+use std::{
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	time::Duration,
+};
+
+use crossbeam::channel::Sender;
+use eframe::egui::Context;
+use serde::Deserialize;
+
+use crate::{app::NeosPeepsApp, notifications::Notification};
+
+mod signalr;
+
+/// How often to poll anyway while connected, in case something was missed.
+const FALLBACK_POLL_INTERVAL: Duration = Duration::from_secs(600);
+const RECONNECT_DELAY_MIN: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY_MAX: Duration = Duration::from_secs(120);
+
+// The client methods that the hub invokes
+const RECEIVE_MESSAGE: &str = "ReceiveMessage";
+const MESSAGE_SENT: &str = "MessageSent";
+const RECEIVE_STATUS_UPDATE: &str = "ReceiveStatusUpdate";
+
+#[derive(Debug)]
+pub enum Event {
+	Connected,
+	Disconnected(String),
+	Status(neos::id::User, neos::UserStatus),
+	Message(neos::Message),
+}
+
+/// The status pushes are otherwise the same as [`neos::UserStatus`] but also
+/// have the user's ID.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StatusUpdate {
+	user_id: neos::id::User,
+	#[serde(flatten)]
+	status: neos::UserStatus,
+}
+
+/// Handle to the connection thread, which stops when this is dropped.
+pub struct Realtime {
+	stop: Arc<AtomicBool>,
+	user_session: neos::UserSession,
+	pub connected: bool,
+}
+
+impl Drop for Realtime {
+	fn drop(&mut self) { self.stop.store(true, Ordering::Relaxed); }
+}
+
+impl NeosPeepsApp {
+	/// Starts or stops the realtime connection to match the settings and login.
+	pub fn manage_realtime(&mut self, ctx: &Context) {
+		let wanted = if self.stored.realtime {
+			self
+				.runtime
+				.neos_api
+				.as_ref()
+				.filter(|api| api.is_authenticated())
+				.zip(self.stored.user_session.as_ref())
+		} else {
+			None
+		};
+
+		match (&self.runtime.realtime, wanted) {
+			(None, None) => {}
+			(Some(realtime), Some((_, user_session)))
+				if realtime.user_session == *user_session => {}
+			(_, wanted) => {
+				let wanted = wanted
+					.map(|(api, user_session)| (api.hub_url(), user_session.clone()));
+				// Stops the previous connection if there was one
+				self.runtime.realtime = None;
+				if let Some((hub_url, user_session)) = wanted {
+					self.runtime.realtime =
+						Some(self.start_realtime(ctx, hub_url, user_session));
+				}
+			}
+		}
+	}
+
+	/// If the realtime connection is up, so that polling isn't needed as often.
+	pub fn realtime_connected(&self) -> bool {
+		self.runtime.realtime.as_ref().is_some_and(|realtime| realtime.connected)
+	}
+
+	/// The frequency to poll something that the realtime connection updates.
+	pub fn polling_frequency(&self, frequency: Duration) -> Duration {
+		if self.realtime_connected() {
+			frequency.max(FALLBACK_POLL_INTERVAL)
+		} else {
+			frequency
+		}
+	}
+
+	fn start_realtime(
+		&self, ctx: &Context, hub_url: String, user_session: neos::UserSession,
+	) -> Realtime {
+		let stop = Arc::new(AtomicBool::new(false));
+		let thread_stop = stop.clone();
+		let authorization = user_session.auth_header();
+		let realtime_sender = self.threads.channels.realtime_sender();
+		let notifications_sender = self.threads.channels.notifications_sender();
+		let ctx = ctx.clone();
+
+		if let Err(err) =
+			std::thread::Builder::new().name("realtime".to_owned()).spawn(move || {
+				run(
+					&hub_url,
+					&authorization,
+					&thread_stop,
+					&realtime_sender,
+					&notifications_sender,
+					&ctx,
+				);
+			}) {
+			self
+				.threads
+				.channels
+				.notifications_sender()
+				.send(Notification::error(
+					"Failed to start the realtime connection",
+					err.to_string(),
+				))
+				.ok();
+		}
+
+		Realtime { stop, user_session, connected: false }
+	}
+}
+
+/// Keeps reconnecting to the hub until stopped.
+fn run(
+	hub_url: &str, authorization: &str, stop: &AtomicBool,
+	realtime_sender: &Sender<Event>, notifications_sender: &Sender<Notification>,
+	ctx: &Context,
+) {
+	let mut delay = RECONNECT_DELAY_MIN;
+
+	while !stop.load(Ordering::Relaxed) {
+		let reason = match signalr::Connection::connect(hub_url, authorization) {
+			Ok(mut connection) => {
+				delay = RECONNECT_DELAY_MIN;
+				realtime_sender.send(Event::Connected).ok();
+				ctx.request_repaint();
+
+				let reason = loop {
+					if stop.load(Ordering::Relaxed) {
+						connection.close();
+						return;
+					}
+					match connection.poll() {
+						Ok(invocations) => {
+							for invocation in invocations {
+								match parse_invocation(invocation) {
+									Ok(Some(event)) => {
+										realtime_sender.send(event).ok();
+									}
+									Ok(None) => {}
+									Err(err) => {
+										notifications_sender
+											.send(Notification::warning(
+												"Failed to parse a realtime update",
+												err,
+											))
+											.ok();
+									}
+								}
+								ctx.request_repaint();
+							}
+						}
+						Err(err) => break err,
+					}
+				};
+				connection.close();
+				reason
+			}
+			Err(err) => err,
+		};
+
+		if stop.load(Ordering::Relaxed) {
+			return;
+		}
+		realtime_sender.send(Event::Disconnected(reason)).ok();
+		ctx.request_repaint();
+
+		let reconnect_at = std::time::Instant::now() + delay;
+		while std::time::Instant::now() < reconnect_at {
+			if stop.load(Ordering::Relaxed) {
+				return;
+			}
+			std::thread::sleep(Duration::from_millis(250));
+		}
+		delay = (delay * 2).min(RECONNECT_DELAY_MAX);
+	}
+}
+
+/// Turns an invocation into an event, ignoring unknown ones.
+fn parse_invocation(
+	invocation: signalr::Invocation,
+) -> Result<Option<Event>, String> {
+	let Some(argument) = invocation.arguments.into_iter().next() else {
+		return Ok(None);
+	};
+
+	Ok(match invocation.target.as_str() {
+		RECEIVE_MESSAGE | MESSAGE_SENT => Some(Event::Message(
+			serde_json::from_value(argument).map_err(|e| e.to_string())?,
+		)),
+		RECEIVE_STATUS_UPDATE => {
+			let update: StatusUpdate =
+				serde_json::from_value(argument).map_err(|e| e.to_string())?;
+			Some(Event::Status(update.user_id, update.status))
+		}
+		_ => None,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use std::time::Instant;
+
+	use super::*;
+	use crate::backend::{stand_in::USER_SESSION, CustomBackend, NeosBackend};
+
+	const STATUS: &str = r#"{
+		"userId": "U-friend",
+		"onlineStatus": "Online",
+		"currentSessionAccessLevel": 0,
+		"currentSessionHidden": false,
+		"currentHosting": false,
+		"outputDevice": 2,
+		"isMobile": false
+	}"#;
+
+	const MESSAGE: &str = r#"{
+		"id": "MSG-test",
+		"ownerId": "U-test",
+		"senderId": "U-friend",
+		"recipientId": "U-test",
+		"messageType": "Text",
+		"content": "Hello",
+		"sendTime": "2023-01-01T00:00:00Z",
+		"lastUpdateTime": "2023-01-01T00:00:00Z"
+	}"#;
+
+	fn invocation(target: &str, arguments: &[&str]) -> signalr::Invocation {
+		signalr::Invocation {
+			target: target.to_owned(),
+			arguments: arguments
+				.iter()
+				.map(|argument| serde_json::from_str(argument).unwrap())
+				.collect(),
+		}
+	}
+
+	#[test]
+	fn status_updates_have_the_user() {
+		let event =
+			parse_invocation(invocation(RECEIVE_STATUS_UPDATE, &[STATUS])).unwrap();
+		let Some(Event::Status(user_id, status)) = event else {
+			panic!("Expected a status, got {event:?}");
+		};
+		assert_eq!(user_id.as_ref(), "U-friend");
+		assert_eq!(status.online_status, neos::OnlineStatus::Online);
+	}
+
+	#[test]
+	fn received_and_sent_messages_are_messages() {
+		for target in [RECEIVE_MESSAGE, MESSAGE_SENT] {
+			let event = parse_invocation(invocation(target, &[MESSAGE])).unwrap();
+			let Some(Event::Message(message)) = event else {
+				panic!("Expected a message, got {event:?}");
+			};
+			assert_eq!(message.id, "MSG-test");
+		}
+	}
+
+	#[test]
+	fn unknown_and_empty_invocations_are_ignored() {
+		assert!(parse_invocation(invocation("Debug", &[MESSAGE]))
+			.unwrap()
+			.is_none());
+		assert!(parse_invocation(invocation(RECEIVE_MESSAGE, &[]))
+			.unwrap()
+			.is_none());
+	}
+
+	#[test]
+	fn invalid_arguments_are_errors() {
+		assert!(
+			parse_invocation(invocation(RECEIVE_STATUS_UPDATE, &["1"])).is_err()
+		);
+		assert!(parse_invocation(invocation(RECEIVE_MESSAGE, &[STATUS])).is_err());
+	}
+
+	#[test]
+	fn pushed_status_reaches_the_app() {
+		let push = format!(
+			"{{\"type\":1,\"target\":\"{RECEIVE_STATUS_UPDATE}\",\"arguments\":[{STATUS}]}}\u{1e}"
+		);
+		let hub_url = signalr::tests::stand_in_hub(vec![push]);
+
+		let backend =
+			CustomBackend::with_hub_url("http://127.0.0.1:9/api".to_owned(), hub_url);
+		let user_session: neos::UserSession =
+			serde_json::from_str(USER_SESSION).unwrap();
+		let friend: neos::Friend = serde_json::from_str(&format!(
+			r#"{{
+				"id": "U-friend",
+				"friendUsername": "Friend",
+				"friendStatus": "Accepted",
+				"isAccepted": true,
+				"userStatus": {},
+				"profile": null,
+				"ownerId": "U-test"
+			}}"#,
+			STATUS.replace("Online", "Offline")
+		))
+		.unwrap();
+
+		let mut app = NeosPeepsApp::default();
+		app.runtime.friends = vec![friend];
+		app.runtime.neos_api = Some(backend.upgrade(user_session.clone()));
+		app.stored.user_session = Some(user_session);
+		app.stored.realtime = true;
+		let ctx = Context::default();
+		app.manage_realtime(&ctx);
+
+		let deadline = Instant::now() + Duration::from_secs(10);
+		while app.runtime.friends[0].status.online_status
+			!= neos::OnlineStatus::Online
+		{
+			assert!(Instant::now() < deadline, "The status wasn't applied");
+			std::thread::sleep(Duration::from_millis(10));
+			app.try_recv(&ctx);
+		}
+		assert!(app.realtime_connected());
+	}
+}
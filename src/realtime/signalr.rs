@@ -0,0 +1,465 @@
+//! A minimal client for the `SignalR` JSON hub protocol over a WebSocket.
+//!
+//! See <https://github.com/dotnet/aspnetcore/blob/main/src/SignalR/docs/specs/HubProtocol.md>
+
+use std::{
+	net::TcpStream,
+	time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use tungstenite::{
+	client::IntoClientRequest,
+	http::HeaderValue,
+	stream::MaybeTlsStream,
+	WebSocket,
+};
+
+/// Every message of the JSON protocol is terminated by this.
+const RECORD_SEPARATOR: char = '\u{1e}';
+const HANDSHAKE: &str = "{\"protocol\":\"json\",\"version\":1}\u{1e}";
+const PING: &str = "{\"type\":6}\u{1e}";
+/// How often the client should let the server know that it's still alive
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+/// Twice the server's default keep alive interval
+const SERVER_TIMEOUT: Duration = Duration::from_secs(30);
+/// How long a read blocks at most, so that the connection can be stopped.
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+/// Azure's `SignalR` service redirects the negotiation once
+const MAX_REDIRECTS: usize = 3;
+
+const INVOCATION: u8 = 1;
+const CLOSE: u8 = 7;
+
+#[derive(Debug, Deserialize)]
+struct HubMessage {
+	#[serde(rename = "type")]
+	kind: u8,
+	target: Option<String>,
+	#[serde(default)]
+	arguments: Vec<serde_json::Value>,
+	error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct NegotiateResponse {
+	connection_token: Option<String>,
+	connection_id: Option<String>,
+	/// Set when redirected to another service, such as Azure `SignalR`
+	url: Option<String>,
+	access_token: Option<String>,
+	error: Option<String>,
+}
+
+/// A call of a client side hub method by the server.
+#[derive(Debug)]
+pub struct Invocation {
+	pub target: String,
+	pub arguments: Vec<serde_json::Value>,
+}
+
+pub struct Connection {
+	socket: WebSocket<MaybeTlsStream<TcpStream>>,
+	last_received: Instant,
+	last_sent: Instant,
+	/// Received along the handshake response
+	pending: Vec<Invocation>,
+}
+
+impl Connection {
+	/// Connects to a hub, negotiating first if the URL isn't a WebSocket one.
+	pub fn connect(hub_url: &str, authorization: &str) -> Result<Self, String> {
+		let (ws_url, authorization) =
+			if hub_url.starts_with("ws://") || hub_url.starts_with("wss://") {
+				// Skipping negotiation is fine when only using WebSockets
+				(hub_url.to_owned(), authorization.to_owned())
+			} else {
+				negotiate(hub_url, authorization)?
+			};
+
+		let mut request =
+			ws_url.into_client_request().map_err(|e| e.to_string())?;
+		request.headers_mut().insert(
+			"Authorization",
+			HeaderValue::from_str(&authorization).map_err(|e| e.to_string())?,
+		);
+		request
+			.headers_mut()
+			.insert("User-Agent", HeaderValue::from_static(crate::USER_AGENT));
+
+		let (mut socket, _) =
+			tungstenite::connect(request).map_err(|e| e.to_string())?;
+		set_read_timeout(&socket).map_err(|e| e.to_string())?;
+
+		socket
+			.write_message(tungstenite::Message::Text(HANDSHAKE.to_owned()))
+			.map_err(|e| e.to_string())?;
+
+		let mut connection = Self {
+			socket,
+			last_received: Instant::now(),
+			last_sent: Instant::now(),
+			pending: Vec::new(),
+		};
+
+		// The handshake response is an empty object, or one with an error
+		let text = loop {
+			if let Some(text) = connection.read_text()? {
+				break text;
+			}
+			if connection.last_received.elapsed() > SERVER_TIMEOUT {
+				return Err("No response to the handshake".to_owned());
+			}
+		};
+		let (handshake, rest) =
+			text.split_once(RECORD_SEPARATOR).unwrap_or((&text, ""));
+		let response: serde_json::Value =
+			serde_json::from_str(handshake).map_err(|e| e.to_string())?;
+		if let Some(error) = response.get("error").and_then(|e| e.as_str()) {
+			return Err(format!("Handshake failed: {error}"));
+		}
+		// Messages might come in the same frame as the handshake response
+		connection.pending = parse_records(rest)?;
+
+		Ok(connection)
+	}
+
+	/// Waits a short while for the next messages, while keeping the connection
+	/// alive.
+	pub fn poll(&mut self) -> Result<Vec<Invocation>, String> {
+		if !self.pending.is_empty() {
+			return Ok(std::mem::take(&mut self.pending));
+		}
+
+		if self.last_sent.elapsed() > PING_INTERVAL {
+			self
+				.socket
+				.write_message(tungstenite::Message::Text(PING.to_owned()))
+				.map_err(|e| e.to_string())?;
+			self.last_sent = Instant::now();
+		}
+
+		match self.read_text()? {
+			Some(text) => parse_records(&text),
+			None if self.last_received.elapsed() > SERVER_TIMEOUT => {
+				Err("The server stopped responding".to_owned())
+			}
+			None => Ok(Vec::new()),
+		}
+	}
+
+	/// Closes the connection, ignoring errors as it's going away anyway.
+	pub fn close(mut self) {
+		self.socket.close(None).ok();
+		self.socket.write_pending().ok();
+	}
+
+	/// Reads the next text frame, or none if the read timed out.
+	fn read_text(&mut self) -> Result<Option<String>, String> {
+		match self.socket.read_message() {
+			Ok(tungstenite::Message::Text(text)) => {
+				self.last_received = Instant::now();
+				Ok(Some(text))
+			}
+			Ok(tungstenite::Message::Close(frame)) => Err(frame.map_or_else(
+				|| "Connection closed".to_owned(),
+				|frame| format!("Connection closed: {}", frame.reason),
+			)),
+			Ok(_) => {
+				self.last_received = Instant::now();
+				Ok(None)
+			}
+			Err(tungstenite::Error::Io(e))
+				if matches!(
+					e.kind(),
+					std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+				) =>
+			{
+				Ok(None)
+			}
+			Err(e) => Err(e.to_string()),
+		}
+	}
+}
+
+/// Parses the invocations out of the separated records.
+fn parse_records(records: &str) -> Result<Vec<Invocation>, String> {
+	let mut invocations = Vec::new();
+	for record in records.split(RECORD_SEPARATOR).filter(|r| !r.is_empty()) {
+		let message: HubMessage =
+			serde_json::from_str(record).map_err(|e| e.to_string())?;
+		match message.kind {
+			INVOCATION => {
+				if let Some(target) = message.target {
+					invocations.push(Invocation { target, arguments: message.arguments });
+				}
+			}
+			CLOSE => {
+				return Err(message.error.map_or_else(
+					|| "The server closed the connection".to_owned(),
+					|error| format!("The server closed the connection: {error}"),
+				));
+			}
+			// Pings only keep the connection alive, and the client doesn't invoke
+			// anything so there's no completions or such either.
+			_ => {}
+		}
+	}
+
+	Ok(invocations)
+}
+
+/// Negotiates the WebSocket URL and the authorization to use with it.
+fn negotiate(
+	hub_url: &str, authorization: &str,
+) -> Result<(String, String), String> {
+	let mut hub_url = hub_url.to_owned();
+	let mut authorization = authorization.to_owned();
+
+	for _ in 0..MAX_REDIRECTS {
+		let response: NegotiateResponse = minreq::post(negotiate_url(&hub_url))
+			.with_header("Authorization", &authorization)
+			.with_header("User-Agent", crate::USER_AGENT)
+			.with_timeout(30)
+			.send()
+			.map_err(|e| e.to_string())?
+			.json()
+			.map_err(|e| e.to_string())?;
+
+		if let Some(error) = response.error {
+			return Err(format!("Negotiation failed: {error}"));
+		}
+
+		if let Some(url) = response.url {
+			hub_url = url;
+			if let Some(access_token) = response.access_token {
+				authorization = "Bearer ".to_owned() + &access_token;
+			}
+			continue;
+		}
+
+		let id = response
+			.connection_token
+			.or(response.connection_id)
+			.ok_or_else(|| "Negotiation didn't return a connection".to_owned())?;
+		return Ok((websocket_url(&hub_url, &id), authorization));
+	}
+
+	Err("Too many negotiation redirects".to_owned())
+}
+
+/// The negotiation endpoint of the hub, keeping the query of the hub URL.
+fn negotiate_url(hub_url: &str) -> String {
+	let (path, query) = hub_url.split_once('?').unwrap_or((hub_url, ""));
+	format!(
+		"{}/negotiate?{query}{}negotiateVersion=1",
+		path.trim_end_matches('/'),
+		if query.is_empty() { "" } else { "&" },
+	)
+}
+
+/// The WebSocket URL of the hub for the negotiated connection.
+fn websocket_url(hub_url: &str, connection: &str) -> String {
+	let ws_url = hub_url.replacen("http", "ws", 1);
+	let separator = if ws_url.contains('?') { '&' } else { '?' };
+	format!("{ws_url}{separator}id={}", encode(connection))
+}
+
+/// Percent encodes everything except unreserved characters.
+fn encode(value: &str) -> String {
+	value
+		.bytes()
+		.map(|b| match b {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+				(b as char).to_string()
+			}
+			_ => format!("%{b:02X}"),
+		})
+		.collect()
+}
+
+fn set_read_timeout(
+	socket: &WebSocket<MaybeTlsStream<TcpStream>>,
+) -> std::io::Result<()> {
+	match socket.get_ref() {
+		MaybeTlsStream::Plain(stream) => {
+			stream.set_read_timeout(Some(READ_TIMEOUT))
+		}
+		MaybeTlsStream::Rustls(stream) => {
+			stream.get_ref().set_read_timeout(Some(READ_TIMEOUT))
+		}
+		_ => Ok(()),
+	}
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+	use std::net::TcpListener;
+
+	use super::*;
+	use crate::backend::stand_in;
+
+	/// Accepts one connection, answers the handshake and sends the frames,
+	/// returning the WebSocket URL of the hub.
+	pub(in crate::realtime) fn stand_in_hub(frames: Vec<String>) -> String {
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let hub_url = format!("ws://{}/hub", listener.local_addr().unwrap());
+
+		std::thread::spawn(move || {
+			let (stream, _) = listener.accept().unwrap();
+			let mut socket = tungstenite::accept(stream).unwrap();
+			let handshake = socket.read_message().unwrap();
+			assert_eq!(handshake.into_text().unwrap(), HANDSHAKE);
+
+			socket
+				.write_message(tungstenite::Message::Text(format!(
+					"{{}}{RECORD_SEPARATOR}"
+				)))
+				.unwrap();
+			for frame in frames {
+				socket.write_message(tungstenite::Message::Text(frame)).unwrap();
+			}
+			// Until the client goes away
+			while socket.read_message().is_ok() {}
+		});
+
+		hub_url
+	}
+
+	fn record(json: &str) -> String { format!("{json}{RECORD_SEPARATOR}") }
+
+	#[test]
+	fn records_are_split_by_the_separator() {
+		let records = record(r#"{"type":1,"target":"First","arguments":[1]}"#)
+			+ &record(r#"{"type":6}"#)
+			+ &record(r#"{"type":1,"target":"Second","arguments":[]}"#);
+
+		let invocations = parse_records(&records).unwrap();
+		let targets: Vec<_> =
+			invocations.iter().map(|invocation| invocation.target.as_str()).collect();
+		assert_eq!(targets, ["First", "Second"]);
+		assert_eq!(invocations[0].arguments, [serde_json::json!(1)]);
+		assert!(invocations[1].arguments.is_empty());
+	}
+
+	#[test]
+	fn pings_and_empty_records_are_skipped() {
+		assert!(parse_records("").unwrap().is_empty());
+		assert!(parse_records(PING).unwrap().is_empty());
+		// Invocations without a target are ones that expect no reply
+		assert!(parse_records(&record(r#"{"type":1}"#)).unwrap().is_empty());
+	}
+
+	#[test]
+	fn close_is_an_error() {
+		let err =
+			parse_records(&record(r#"{"type":7,"error":"Going away"}"#)).unwrap_err();
+		assert_eq!(err, "The server closed the connection: Going away");
+
+		let err = parse_records(&record(r#"{"type":7}"#)).unwrap_err();
+		assert_eq!(err, "The server closed the connection");
+	}
+
+	#[test]
+	fn invalid_records_are_errors() {
+		assert!(parse_records(&record("{\"type\":")).is_err());
+		assert!(parse_records(&record(r#"{"target":"Missing type"}"#)).is_err());
+	}
+
+	#[test]
+	fn encodes_reserved_characters() {
+		assert_eq!(encode("AZaz09-_.~"), "AZaz09-_.~");
+		assert_eq!(encode("a b/c+d=="), "a%20b%2Fc%2Bd%3D%3D");
+		assert_eq!(encode("ä"), "%C3%A4");
+	}
+
+	#[test]
+	fn negotiate_url_keeps_the_query() {
+		assert_eq!(
+			negotiate_url("https://example.com/hub"),
+			"https://example.com/hub/negotiate?negotiateVersion=1"
+		);
+		assert_eq!(
+			negotiate_url("https://example.com/hub/"),
+			"https://example.com/hub/negotiate?negotiateVersion=1"
+		);
+		assert_eq!(
+			negotiate_url("https://example.com/client/?hub=neos"),
+			"https://example.com/client/negotiate?hub=neos&negotiateVersion=1"
+		);
+	}
+
+	#[test]
+	fn websocket_url_has_the_connection() {
+		assert_eq!(
+			websocket_url("https://example.com/hub", "a+b"),
+			"wss://example.com/hub?id=a%2Bb"
+		);
+		assert_eq!(
+			websocket_url("http://example.com/client/?hub=neos", "id"),
+			"ws://example.com/client/?hub=neos&id=id"
+		);
+	}
+
+	#[test]
+	fn negotiation_follows_redirects() {
+		let (base, received) = stand_in::serve(vec![
+			(200, r#"{"url":"$BASE/client/?hub=neos","accessToken":"azure"}"#),
+			(200, r#"{"connectionToken":"a b","connectionId":"unused"}"#),
+		]);
+		let hub_url = format!("{base}/hub");
+
+		let (ws_url, authorization) =
+			negotiate(&hub_url, "neos U-test:secret").unwrap();
+		assert_eq!(
+			ws_url,
+			format!("{}/client/?hub=neos&id=a%20b", base.replacen("http", "ws", 1))
+		);
+		assert_eq!(authorization, "Bearer azure");
+
+		let first = received.recv().unwrap();
+		assert_eq!(
+			first.request_line,
+			"POST /hub/negotiate?negotiateVersion=1 HTTP/1.1"
+		);
+		assert!(first.has_header("Authorization: neos U-test:secret"));
+		let second = received.recv().unwrap();
+		assert_eq!(
+			second.request_line,
+			"POST /client/negotiate?hub=neos&negotiateVersion=1 HTTP/1.1"
+		);
+		assert!(second.has_header("Authorization: Bearer azure"));
+	}
+
+	#[test]
+	fn negotiation_errors_are_returned() {
+		let (base, _received) =
+			stand_in::serve(vec![(200, r#"{"error":"Unauthorized"}"#)]);
+		assert_eq!(
+			negotiate(&format!("{base}/hub"), "").unwrap_err(),
+			"Negotiation failed: Unauthorized"
+		);
+	}
+
+	#[test]
+	fn connects_and_receives_invocations() {
+		let hub_url = stand_in_hub(vec![
+			record(r#"{"type":6}"#),
+			record(r#"{"type":1,"target":"Pushed","arguments":["hello"]}"#),
+		]);
+		let mut connection =
+			Connection::connect(&hub_url, "neos U-test:secret").unwrap();
+
+		let deadline = Instant::now() + Duration::from_secs(10);
+		let invocation = loop {
+			assert!(Instant::now() < deadline, "Nothing was received");
+			if let Some(invocation) = connection.poll().unwrap().pop() {
+				break invocation;
+			}
+		};
+		assert_eq!(invocation.target, "Pushed");
+		assert_eq!(invocation.arguments, [serde_json::json!("hello")]);
+		connection.close();
+	}
+}
@@ -0,0 +1,475 @@
+//! Neos' Unity style rich text tags, such as `<color=red>` and `<b>`, which
+//! are used in session names and messages.
+
+use eframe::egui::{
+	text::LayoutJob,
+	Color32,
+	RichText,
+	Stroke,
+	Style,
+	TextFormat,
+	TextStyle,
+	Ui,
+	WidgetText,
+};
+
+use crate::app::NeosPeepsApp;
+
+/// Relative sizes are clamped to this, so that text can't take over the rows
+const SIZE_RANGE: std::ops::RangeInclusive<f32> = 0.5..=2.0;
+
+/// Tags that are dropped without affecting the text
+const IGNORED_TAGS: [&str; 26] = [
+	"align",
+	"alpha",
+	"cspace",
+	"font",
+	"gradient",
+	"indent",
+	"line-height",
+	"line-indent",
+	"link",
+	"lowercase",
+	"margin",
+	"mark",
+	"material",
+	"mspace",
+	"nobr",
+	"noparse",
+	"page",
+	"pos",
+	"rotate",
+	"smallcaps",
+	"space",
+	"sprite",
+	"style",
+	"sub",
+	"sup",
+	"uppercase",
+];
+
+/// The colours that can be used by name
+const NAMED_COLORS: [(&str, Color32); 23] = [
+	("aqua", Color32::from_rgb(0, 255, 255)),
+	("black", Color32::BLACK),
+	("blue", Color32::from_rgb(0, 0, 255)),
+	("brown", Color32::from_rgb(165, 42, 42)),
+	("cyan", Color32::from_rgb(0, 255, 255)),
+	("darkblue", Color32::from_rgb(0, 0, 160)),
+	("fuchsia", Color32::from_rgb(255, 0, 255)),
+	("gray", Color32::from_rgb(128, 128, 128)),
+	("green", Color32::from_rgb(0, 128, 0)),
+	("grey", Color32::from_rgb(128, 128, 128)),
+	("lightblue", Color32::from_rgb(173, 216, 230)),
+	("lime", Color32::from_rgb(0, 255, 0)),
+	("magenta", Color32::from_rgb(255, 0, 255)),
+	("maroon", Color32::from_rgb(128, 0, 0)),
+	("navy", Color32::from_rgb(0, 0, 128)),
+	("olive", Color32::from_rgb(128, 128, 0)),
+	("orange", Color32::from_rgb(255, 165, 0)),
+	("purple", Color32::from_rgb(128, 0, 128)),
+	("red", Color32::from_rgb(255, 0, 0)),
+	("silver", Color32::from_rgb(192, 192, 192)),
+	("teal", Color32::from_rgb(0, 128, 128)),
+	("white", Color32::WHITE),
+	("yellow", Color32::from_rgb(255, 255, 0)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TagName {
+	Color,
+	Size,
+	Bold,
+	Italic,
+	Underline,
+	Strikethrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Tag {
+	/// Along with the value of the tag if it has one
+	Open(TagName, Option<Color32>, Option<f32>),
+	Close(TagName),
+	Ignored,
+}
+
+/// The formatting of a part of the text
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct SpanStyle {
+	color: Option<Color32>,
+	/// Relative to the normal size
+	size: Option<f32>,
+	bold: bool,
+	italic: bool,
+	underline: bool,
+	strikethrough: bool,
+}
+
+/// The currently open tags
+#[derive(Debug, Default)]
+struct State {
+	colors: Vec<Color32>,
+	sizes: Vec<f32>,
+	bold: usize,
+	italic: usize,
+	underline: usize,
+	strikethrough: usize,
+}
+
+impl State {
+	fn style(&self) -> SpanStyle {
+		SpanStyle {
+			color: self.colors.last().copied(),
+			size: self.sizes.last().copied(),
+			bold: self.bold > 0,
+			italic: self.italic > 0,
+			underline: self.underline > 0,
+			strikethrough: self.strikethrough > 0,
+		}
+	}
+
+	fn apply(&mut self, tag: Tag) {
+		match tag {
+			Tag::Open(TagName::Color, color, _) => self.colors.extend(color),
+			Tag::Open(TagName::Size, _, size) => {
+				// Unsupported sizes keep the current one, until their closing tag
+				let current = self.sizes.last().copied().unwrap_or(1_f32);
+				self.sizes.push(size.unwrap_or(current));
+			}
+			Tag::Open(name, _, _) => {
+				if let Some(counter) = self.counter(name) {
+					*counter += 1;
+				}
+			}
+			Tag::Close(TagName::Color) => {
+				self.colors.pop();
+			}
+			Tag::Close(TagName::Size) => {
+				self.sizes.pop();
+			}
+			Tag::Close(name) => {
+				if let Some(counter) = self.counter(name) {
+					*counter = counter.saturating_sub(1);
+				}
+			}
+			Tag::Ignored => {}
+		}
+	}
+
+	/// Of the tags that can be nested without a value
+	const fn counter(&mut self, name: TagName) -> Option<&mut usize> {
+		match name {
+			TagName::Bold => Some(&mut self.bold),
+			TagName::Italic => Some(&mut self.italic),
+			TagName::Underline => Some(&mut self.underline),
+			TagName::Strikethrough => Some(&mut self.strikethrough),
+			TagName::Color | TagName::Size => None,
+		}
+	}
+}
+
+fn parse_color(value: &str) -> Option<Color32> {
+	let Some(hex) = value.strip_prefix('#') else {
+		let value = value.to_lowercase();
+		return NAMED_COLORS
+			.iter()
+			.find(|(name, _)| *name == value)
+			.map(|(_, color)| *color);
+	};
+	if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+
+	let channel = |i: usize, len: usize| {
+		let value =
+			u8::from_str_radix(hex.get(i * len..(i + 1) * len)?, 16).ok()?;
+		// Short hex colours repeat each digit, so F is FF
+		Some(if len == 1 { value * 17 } else { value })
+	};
+	let (len, has_alpha) = match hex.len() {
+		3 => (1, false),
+		4 => (1, true),
+		6 => (2, false),
+		8 => (2, true),
+		_ => return None,
+	};
+	let alpha = if has_alpha { channel(3, len)? } else { u8::MAX };
+
+	Some(Color32::from_rgba_unmultiplied(
+		channel(0, len)?,
+		channel(1, len)?,
+		channel(2, len)?,
+		alpha,
+	))
+}
+
+/// Only relative sizes are supported, as absolute ones were made for the
+/// unknown size of the text in Neos.
+fn parse_size(value: &str) -> Option<f32> {
+	let size = if let Some(percent) = value.strip_suffix('%') {
+		percent.trim().parse::<f32>().ok()? / 100_f32
+	} else {
+		value.strip_suffix("em")?.trim().parse::<f32>().ok()?
+	};
+
+	size.is_finite().then(|| size.clamp(*SIZE_RANGE.start(), *SIZE_RANGE.end()))
+}
+
+/// Parses the inside of `<...>`, returning [`None`] if it's not a tag.
+fn parse_tag(tag: &str) -> Option<Tag> {
+	let (name, value) = match tag.split_once('=') {
+		Some((name, value)) => (name, Some(value.trim().trim_matches('"'))),
+		None => (tag, None),
+	};
+	// Not trimmed, so that text like `a < b > c` isn't a tag
+	let name = name.to_lowercase();
+
+	// Unity allows colours without the tag name
+	if name.starts_with('#') && value.is_none() {
+		return parse_color(&name)
+			.map(|color| Tag::Open(TagName::Color, Some(color), None));
+	}
+
+	let (closing, name) =
+		name.strip_prefix('/').map_or((false, name.as_str()), |name| (true, name));
+	let tag_name = match name {
+		"color" => TagName::Color,
+		"size" => TagName::Size,
+		"b" => TagName::Bold,
+		"i" => TagName::Italic,
+		"u" => TagName::Underline,
+		"s" => TagName::Strikethrough,
+		_ if IGNORED_TAGS.contains(&name) => return Some(Tag::Ignored),
+		_ => return None,
+	};
+
+	if closing {
+		return Some(Tag::Close(tag_name));
+	}
+	match tag_name {
+		TagName::Color => {
+			Some(Tag::Open(tag_name, Some(parse_color(value?)?), None))
+		}
+		TagName::Size => {
+			Some(Tag::Open(tag_name, None, value.and_then(parse_size)))
+		}
+		_ => Some(Tag::Open(tag_name, None, None)),
+	}
+}
+
+/// Splits the text into parts with the formatting of their tags, keeping
+/// anything that isn't a tag as text.
+fn parse(text: &str) -> Vec<(String, SpanStyle)> {
+	let mut spans = Vec::new();
+	let mut state = State::default();
+	let mut current = String::new();
+
+	let mut rest = text;
+	while let Some(start) = rest.find('<') {
+		current.push_str(&rest[..start]);
+		let after = &rest[start + 1..];
+		let tag = after
+			.find('>')
+			.map(|end| &after[..end])
+			.filter(|tag| !tag.contains('<'))
+			.and_then(|tag| parse_tag(tag).map(|parsed| (tag.len(), parsed)));
+
+		let Some((len, tag)) = tag else {
+			current.push('<');
+			rest = after;
+			continue;
+		};
+		if !current.is_empty() {
+			spans.push((std::mem::take(&mut current), state.style()));
+		}
+		state.apply(tag);
+		rest = &after[len + 1..];
+	}
+	current.push_str(rest);
+	if !current.is_empty() {
+		spans.push((current, state.style()));
+	}
+
+	spans
+}
+
+/// The text without any of the tags.
+pub fn strip(text: &str) -> String {
+	parse(text).into_iter().map(|(text, _)| text).collect()
+}
+
+/// Lays out the text with the formatting of its tags, in the text style and
+/// colour where the tags don't change them.
+pub fn layout_job(
+	text: &str, style: &Style, text_style: &TextStyle, color: Color32,
+) -> LayoutJob {
+	let font_id = text_style.resolve(style);
+	let mut job = LayoutJob::default();
+
+	for (text, span) in parse(text) {
+		let mut font_id = font_id.clone();
+		font_id.size *= span.size.unwrap_or(1_f32);
+		// There's no bold font, so it's shown like egui's strong text instead
+		let color = span.color.unwrap_or_else(|| {
+			if span.bold {
+				style.visuals.strong_text_color()
+			} else {
+				color
+			}
+		});
+		let line = |enabled: bool| {
+			if enabled {
+				Stroke::new(1_f32, color)
+			} else {
+				Stroke::NONE
+			}
+		};
+
+		job.append(
+			&text,
+			0_f32,
+			TextFormat {
+				font_id,
+				color,
+				italics: span.italic,
+				underline: line(span.underline),
+				strikethrough: line(span.strikethrough),
+				..TextFormat::default()
+			},
+		);
+	}
+
+	job
+}
+
+impl NeosPeepsApp {
+	/// The formatted text, or the stripped text if formatting is disabled.
+	pub fn rich_text(
+		&self, ui: &Ui, text: &str, text_style: TextStyle, color: Option<Color32>,
+	) -> WidgetText {
+		let color = color.unwrap_or_else(|| ui.visuals().text_color());
+		if self.stored.rich_text {
+			layout_job(text, ui.style(), &text_style, color).into()
+		} else {
+			RichText::new(strip(text)).text_style(text_style).color(color).into()
+		}
+	}
+
+	pub fn session_name(
+		&self, ui: &Ui, session: &neos::SessionInfo, text_style: TextStyle,
+		color: Option<Color32>,
+	) -> WidgetText {
+		if self.stored.rich_text {
+			self.rich_text(ui, &session.name, text_style, color)
+		} else {
+			let color = color.unwrap_or_else(|| ui.visuals().text_color());
+			RichText::new(session.stripped_name())
+				.text_style(text_style)
+				.color(color)
+				.into()
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn span(text: &str, style: SpanStyle) -> (String, SpanStyle) {
+		(text.to_owned(), style)
+	}
+
+	const BOLD: SpanStyle = SpanStyle {
+		color: None,
+		size: None,
+		bold: true,
+		italic: false,
+		underline: false,
+		strikethrough: false,
+	};
+
+	#[test]
+	fn nested_tags_combine() {
+		let italic = SpanStyle { italic: true, ..SpanStyle::default() };
+		assert_eq!(
+			parse("<i>a<b>b</b>c</i>d"),
+			[
+				span("a", italic),
+				span("b", SpanStyle { bold: true, ..italic }),
+				span("c", italic),
+				span("d", SpanStyle::default())
+			]
+		);
+
+		let red = Color32::from_rgb(255, 0, 0);
+		let blue = Color32::from_rgb(0, 0, 255);
+		let spans = parse("<color=red>a<color=blue>b</color>c</color>");
+		let colors: Vec<_> = spans.iter().map(|(_, style)| style.color).collect();
+		assert_eq!(colors, [Some(red), Some(blue), Some(red)]);
+	}
+
+	#[test]
+	fn stray_brackets_are_text() {
+		for text in ["I <3 you", "a < b > c", "1 <", "> 2", "<unknown>", "<b"] {
+			assert_eq!(strip(text), text);
+		}
+		assert_eq!(
+			parse("<<b>x"),
+			[span("<", SpanStyle::default()), span("x", BOLD)]
+		);
+	}
+
+	#[test]
+	fn tags_are_case_insensitive_and_ignored_ones_dropped() {
+		assert_eq!(parse("<B>x</b>"), [span("x", BOLD)]);
+		assert_eq!(strip("<noparse>a</noparse><sprite=1>b"), "ab");
+	}
+
+	#[test]
+	fn hex_colors() {
+		assert_eq!(parse_color("#F00"), Some(Color32::from_rgb(255, 0, 0)));
+		assert_eq!(
+			parse_color("#00ff0080"),
+			Some(Color32::from_rgba_unmultiplied(0, 255, 0, 128))
+		);
+		assert_eq!(
+			parse_color("#0008"),
+			Some(Color32::from_rgba_unmultiplied(0, 0, 0, 136))
+		);
+		assert_eq!(parse_color("Lime"), Some(Color32::from_rgb(0, 255, 0)));
+		assert_eq!(parse_color("#12345"), None);
+		assert_eq!(parse_color("#ggg"), None);
+		assert_eq!(parse_color("nocolor"), None);
+		// Without the tag name
+		assert_eq!(parse("<#fff>x")[0].1.color, Some(Color32::WHITE));
+	}
+
+	#[test]
+	fn sizes_are_relative_and_clamped() {
+		assert_eq!(parse_size("150%"), Some(1.5));
+		assert_eq!(parse_size("0.75em"), Some(0.75));
+		assert_eq!(parse_size("500%"), Some(*SIZE_RANGE.end()));
+		assert_eq!(parse_size("0.1em"), Some(*SIZE_RANGE.start()));
+		assert_eq!(parse_size("12"), None);
+		assert_eq!(parse_size("NaN%"), None);
+		assert_eq!(parse_size("inf em"), None);
+
+		// An unsupported size keeps the current one until it's closed
+		let sizes: Vec<_> = parse("<size=2em>a<size=12>b</size>c</size>d")
+			.into_iter()
+			.map(|(_, style)| style.size)
+			.collect();
+		assert_eq!(sizes, [Some(2.0), Some(2.0), Some(2.0), None]);
+	}
+
+	#[test]
+	fn unclosed_and_unopened_tags() {
+		assert_eq!(
+			parse("a<b>b"),
+			[span("a", SpanStyle::default()), span("b", BOLD)]
+		);
+		assert_eq!(parse("</b>a"), [span("a", SpanStyle::default())]);
+		assert_eq!(parse("</color>a"), [span("a", SpanStyle::default())]);
+	}
+}
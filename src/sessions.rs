@@ -1,32 +1,27 @@
 use eframe::egui::Context;
-use neos::api_client::{AnyNeos, Neos};
 
-use crate::app::NeosPeepsApp;
+use crate::{app::NeosPeepsApp, error::PeepsError};
 
 impl NeosPeepsApp {
 	/// Refreshes sessions in a background thread
-	pub fn refresh_sessions(&mut self, ctx: &Context) {
+	pub fn refresh_sessions(&self, ctx: &Context) {
 		use rayon::prelude::*;
 
-		let neos_api_arc = match &self.runtime.neos_api {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 
-		self.threads.loading.sessions.set(true);
+		let ticket = self.threads.loading.sessions.start();
 		let sessions_sender = self.threads.channels.sessions_sender();
-		self.threads.spawn_data_op(move || {
-			if let AnyNeos::Authenticated(neos_api) = &*neos_api_arc {
-				match neos_api.get_sessions() {
-					Ok(mut sessions) => {
-						sessions.par_sort_by(|s1, s2| {
-							s1.active_users.cmp(&s2.active_users).reverse()
-						});
-						sessions_sender.send(Ok(sessions)).unwrap();
-					}
-					Err(e) => sessions_sender.send(Err(e.to_string())).unwrap(),
-				}
+		self.threads.spawn_data_op(move || match neos_api.get_sessions() {
+			Ok(mut sessions) => {
+				sessions.par_sort_by(|s1, s2| {
+					s1.active_users.cmp(&s2.active_users).reverse()
+				});
+				sessions_sender.send((ticket, Ok(sessions))).unwrap();
 			}
+			Err(e) => sessions_sender.send((ticket, Err(e.into()))).unwrap(),
 		});
 
 		ctx.request_repaint();
@@ -47,16 +42,36 @@ impl NeosPeepsApp {
 			*self.runtime.session_window.borrow_mut() = Some((id.clone(), None));
 		}
 
-		self.threads.loading.session.set(true);
+		self.threads.loading.session.restart();
 		let id = id.clone();
 		let session_sender = self.threads.channels.session_sender();
+		let generation = self.threads.generations.session.clone();
+		let current = generation.next();
 		self.threads.spawn_data_op(move || {
-			let res = neos_api.get_session(id);
-			session_sender.send(res.map_err(|e| e.to_string())).unwrap();
+			// Was cancelled or replaced while waiting in the queue
+			if !generation.is_current(current) {
+				return;
+			}
+			let res = neos_api.get_session(&id);
+			session_sender.send((current, res.map_err(PeepsError::from))).unwrap();
 		});
 
 		ctx.request_repaint();
 	}
+
+	/// Opens the session window with already known details
+	pub fn open_session(&self, session: &neos::SessionInfo) {
+		self.close_session_window();
+		*self.runtime.session_window.borrow_mut() =
+			Some((session.id.clone(), Some(session.clone())));
+	}
+
+	/// Closes the session window, ignoring the replies to its pending requests
+	pub fn close_session_window(&self) {
+		self.threads.generations.session.invalidate();
+		self.threads.loading.session.reset();
+		*self.runtime.session_window.borrow_mut() = None;
+	}
 }
 
 pub fn find_focused_session<'a>(
@@ -69,10 +84,7 @@ pub fn find_focused_session<'a>(
 			.users
 			.par_iter()
 			.find_any(|user| {
-				user
-					.id
-					.as_ref()
-					.map_or(false, |user_id| user_id == id && user.is_present)
+				user.id.as_ref().is_some_and(|user_id| user_id == id && user.is_present)
 			})
 			.is_some()
 	})
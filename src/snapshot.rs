@@ -0,0 +1,148 @@
+//! The last known friends, sessions and messages, saved to disk so that they
+//! can be shown at startup and while offline.
+
+use std::{
+	path::PathBuf,
+	time::{Duration, Instant},
+};
+
+use eframe::egui::Context;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::{
+	app::NeosPeepsApp,
+	messages::MESSAGES_PAGE,
+	notifications::Notification,
+};
+
+/// How long changes are collected before saving them, as the data changes
+/// after each refresh.
+const SAVE_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+	#[serde(with = "time::serde::rfc3339")]
+	time: OffsetDateTime,
+	/// The snapshot is only shown to the same user
+	user_id: neos::id::User,
+	friends: Vec<neos::Friend>,
+	sessions: Vec<neos::SessionInfo>,
+	/// The latest page of each conversation, split again when loading.
+	messages: Vec<neos::Message>,
+}
+
+fn get_path() -> PathBuf { crate::DATA_DIR.join("snapshot.json") }
+
+impl NeosPeepsApp {
+	/// Saves the current data a while later, along with the other changes by
+	/// then.
+	pub fn save_snapshot(&mut self) {
+		self
+			.runtime
+			.snapshot_due
+			.get_or_insert_with(|| Instant::now() + SAVE_DELAY);
+	}
+
+	/// Saves the current data on the next frame, such as after forgetting some
+	/// of it.
+	pub fn save_snapshot_now(&mut self) {
+		self.runtime.snapshot_due = Some(Instant::now());
+	}
+
+	/// Saves the data in a background thread if it's time to.
+	pub fn save_due_snapshot(&mut self, ctx: &Context) {
+		let Some(due) = self.runtime.snapshot_due else {
+			return;
+		};
+		let remaining = due.saturating_duration_since(Instant::now());
+		if !remaining.is_zero() {
+			ctx.request_repaint_after(remaining);
+			return;
+		}
+		self.runtime.snapshot_due = None;
+
+		let Some(user_session) = &self.stored.user_session else {
+			return;
+		};
+
+		let user_id = user_session.user_id.clone();
+		let friends = self.runtime.friends.clone();
+		let sessions = self.runtime.sessions.clone();
+		// The rest are in the message history
+		let messages: Vec<Vec<neos::Message>> = self
+			.runtime
+			.messages
+			.values()
+			.map(|messages| {
+				let latest = messages.len().saturating_sub(MESSAGES_PAGE.into());
+				messages[latest..].iter().map(|message| message.0.clone()).collect()
+			})
+			.collect();
+
+		let notifications_sender = self.threads.channels.notifications_sender();
+		self.threads.spawn_data_op(move || {
+			let snapshot = Snapshot {
+				time: OffsetDateTime::now_utc(),
+				user_id,
+				friends,
+				sessions,
+				messages: messages.into_iter().flatten().collect(),
+			};
+
+			let res = serde_json::to_vec(&snapshot)
+				.map_err(|e| e.to_string())
+				.and_then(|bytes| crate::atomic_write(&get_path(), &bytes));
+
+			if let Err(err) = res {
+				notifications_sender
+					.send(Notification::warning("Failed to save offline snapshot", err))
+					.ok();
+			}
+		});
+	}
+
+	/// Shows the last saved data of the logged in user, until it's refreshed.
+	pub fn load_snapshot(&mut self) {
+		let Some(user_session) = &self.stored.user_session else {
+			return;
+		};
+
+		// Not having a snapshot yet is fine
+		let Ok(bytes) = std::fs::read(get_path()) else {
+			return;
+		};
+
+		let snapshot: Snapshot = match serde_json::from_slice(&bytes) {
+			Ok(snapshot) => snapshot,
+			Err(err) => {
+				self.runtime.notifications.push(Notification::warning(
+					"Failed to load offline snapshot",
+					err.to_string(),
+				));
+				return;
+			}
+		};
+
+		if snapshot.user_id != user_session.user_id {
+			return;
+		}
+
+		self.runtime.friends = snapshot.friends;
+		self.runtime.sessions = snapshot.sessions;
+		self.runtime.messages = Self::split_by_user(snapshot.messages);
+		for messages in self.runtime.messages.values_mut() {
+			self.stored.message_retention.apply_to_messages(messages);
+		}
+		self.runtime.data_revision += 1;
+		self.update_unread_counts();
+		self.runtime.stale_since = Some(snapshot.time);
+	}
+
+	/// Forgets the saved data, for example when logging out.
+	pub fn remove_snapshot(&mut self) {
+		self.runtime.stale_since = None;
+		self.runtime.snapshot_due = None;
+		std::fs::remove_file(get_path()).ok();
+	}
+}
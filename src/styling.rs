@@ -19,7 +19,7 @@ pub fn setup_styles(ctx: &Context) {
 	ctx.set_style(style);
 }
 
-fn setup_style(_ctx: &Context, style: &mut Style) {
+const fn setup_style(_ctx: &Context, style: &mut Style) {
 	#[cfg(debug_assertions)]
 	{
 		style.debug.debug_on_hover = true;
@@ -134,13 +134,13 @@ fn setup_fonts(ctx: &Context, style: &mut Style) {
 						.unwrap()
 						.push("noto-cjk-jp".to_owned());
 				} else {
-					eprintln!("Failed to load the data of {JP_FONT}. {JP_FONT_ERR}");
+					log::warn!("Failed to load the data of {JP_FONT}. {JP_FONT_ERR}");
 				}
 			} else {
-				eprintln!("Failed to load {JP_FONT}. {JP_FONT_ERR}");
+				log::warn!("Failed to load {JP_FONT}. {JP_FONT_ERR}");
 			}
 		} else {
-			eprintln!("Couldn't find {JP_FONT}. {JP_FONT_ERR}");
+			log::warn!("Couldn't find {JP_FONT}. {JP_FONT_ERR}");
 		}
 	}
 
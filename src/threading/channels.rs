@@ -1,51 +1,92 @@
 use std::sync::Arc;
 
 use crossbeam::channel::{unbounded, Receiver, Sender, TryIter};
-use eframe::egui::TextureHandle;
-use neos::api_client::AnyNeos;
 
-use crate::{messages::AllMessages, updating::GiteaReleasesResponse};
-
-type ImageMsg = (String, Option<TextureHandle>);
+use super::loading::Ticket;
+use crate::{
+	backend::NeosBackend,
+	data::{Texture, TextureKey},
+	error::PeepsError,
+	messages::AllMessages,
+	notifications::Notification,
+	realtime,
+	updating::GiteaReleasesResponse,
+};
+
+type ImageMsg = (TextureKey, Result<Texture, String>);
 type UserStatusMsg = (neos::id::User, neos::UserStatus);
-
-// Sender & Receiver than can have errors.
-type Res<T> = Result<T, String>;
-type ResSender<T> = Sender<Res<T>>;
-type ResReceiver<T> = Receiver<Res<T>>;
+type AuthMsg = Arc<dyn NeosBackend>;
+/// The page as returned by the API, so that its size can be checked
+type OlderMessagesMsg = (neos::id::User, Res<Vec<neos::Message>>);
+/// The ID of a message that was tried to be sent
+type SentMsg = (String, Res<()>);
+/// The ID's of the messages that were marked as read
+type MarkedReadMsg = (neos::id::User, Res<Vec<String>>);
+type HistoryMsg = (u64, AllMessages);
+
+type Res<T> = Result<T, PeepsError>;
+// Replies to requests that are tagged with a generation.
+type Gen<T> = (u64, Res<T>);
+type GenSender<T> = Sender<Gen<T>>;
+type GenReceiver<T> = Receiver<Gen<T>>;
+// Replies to operations that are tracked with a loading ticket.
+type Ticketed<T> = (Ticket, Res<T>);
+type TicketSender<T> = Sender<Ticketed<T>>;
+type TicketReceiver<T> = Receiver<Ticketed<T>>;
 
 #[derive(Debug)]
 pub struct Channels {
 	/// Messages bg refresh
-	messages: (ResSender<AllMessages>, ResReceiver<AllMessages>),
+	refreshed_messages: (TicketSender<AllMessages>, TicketReceiver<AllMessages>),
+	/// Messages of a conversation, such as after sending one
+	messages: (TicketSender<AllMessages>, TicketReceiver<AllMessages>),
+	/// Pages of a conversation's older messages
+	older_messages: (Sender<OlderMessagesMsg>, Receiver<OlderMessagesMsg>),
+	/// Results of sending messages
+	sent: (Sender<SentMsg>, Receiver<SentMsg>),
+	/// Conversations marked as read
+	marked_read: (Sender<MarkedReadMsg>, Receiver<MarkedReadMsg>),
+	/// Messages loaded from the disk, with the generation of the history
+	history: (Sender<HistoryMsg>, Receiver<HistoryMsg>),
 	/// Friends bg refresh
-	friends: (ResSender<Vec<neos::Friend>>, ResReceiver<Vec<neos::Friend>>),
+	friends: (TicketSender<Vec<neos::Friend>>, TicketReceiver<Vec<neos::Friend>>),
 	/// Users search
-	users: (ResSender<Vec<neos::User>>, ResReceiver<Vec<neos::User>>),
+	users: (TicketSender<Vec<neos::User>>, TicketReceiver<Vec<neos::User>>),
 	/// Sessions bg refresh
-	sessions:
-		(ResSender<Vec<neos::SessionInfo>>, ResReceiver<Vec<neos::SessionInfo>>),
+	sessions: (
+		TicketSender<Vec<neos::SessionInfo>>,
+		TicketReceiver<Vec<neos::SessionInfo>>,
+	),
 	/// Login/Logout
-	auth: (Sender<Arc<AnyNeos>>, Receiver<Arc<AnyNeos>>),
+	auth: (Sender<AuthMsg>, Receiver<AuthMsg>),
 	/// New login was successful
 	user_session:
 		(Sender<Option<neos::UserSession>>, Receiver<Option<neos::UserSession>>),
 	/// Image assets being loaded
 	image: (Sender<ImageMsg>, Receiver<ImageMsg>),
 	/// Lookups for the user window
-	user: (ResSender<neos::User>, ResReceiver<neos::User>),
+	user: (GenSender<neos::User>, GenReceiver<neos::User>),
 	/// Lookups for the user window
-	user_status: (ResSender<UserStatusMsg>, ResReceiver<UserStatusMsg>),
+	user_status: (GenSender<UserStatusMsg>, GenReceiver<UserStatusMsg>),
 	/// Lookups for the session window
-	session: (ResSender<neos::SessionInfo>, ResReceiver<neos::SessionInfo>),
+	session: (GenSender<neos::SessionInfo>, GenReceiver<neos::SessionInfo>),
 	update_check:
 		(Sender<GiteaReleasesResponse>, Receiver<GiteaReleasesResponse>),
+	/// Errors and such from the background threads
+	notifications: (Sender<Notification>, Receiver<Notification>),
+	/// Pushes from the realtime connection
+	realtime: (Sender<realtime::Event>, Receiver<realtime::Event>),
 }
 
 impl Default for Channels {
 	fn default() -> Self {
 		Self {
+			refreshed_messages: unbounded(),
 			messages: unbounded(),
+			older_messages: unbounded(),
+			sent: unbounded(),
+			marked_read: unbounded(),
+			history: unbounded(),
 			friends: unbounded(),
 			users: unbounded(),
 			auth: unbounded(),
@@ -56,29 +97,47 @@ impl Default for Channels {
 			user_status: unbounded(),
 			session: unbounded(),
 			update_check: unbounded(),
+			notifications: unbounded(),
+			realtime: unbounded(),
 		}
 	}
 }
 
 // Allow trying to receive or getting a sender but nothing more.
 impl Channels {
-	pub fn messages_sender(&self) -> ResSender<AllMessages> {
+	pub fn refreshed_messages_sender(&self) -> TicketSender<AllMessages> {
+		self.refreshed_messages.0.clone()
+	}
+
+	pub fn messages_sender(&self) -> TicketSender<AllMessages> {
 		self.messages.0.clone()
 	}
 
-	pub fn friends_sender(&self) -> ResSender<Vec<neos::Friend>> {
+	pub fn older_messages_sender(&self) -> Sender<OlderMessagesMsg> {
+		self.older_messages.0.clone()
+	}
+
+	pub fn sent_sender(&self) -> Sender<SentMsg> { self.sent.0.clone() }
+
+	pub fn marked_read_sender(&self) -> Sender<MarkedReadMsg> {
+		self.marked_read.0.clone()
+	}
+
+	pub fn history_sender(&self) -> Sender<HistoryMsg> { self.history.0.clone() }
+
+	pub fn friends_sender(&self) -> TicketSender<Vec<neos::Friend>> {
 		self.friends.0.clone()
 	}
 
-	pub fn users_sender(&self) -> ResSender<Vec<neos::User>> {
+	pub fn users_sender(&self) -> TicketSender<Vec<neos::User>> {
 		self.users.0.clone()
 	}
 
-	pub fn sessions_sender(&self) -> ResSender<Vec<neos::SessionInfo>> {
+	pub fn sessions_sender(&self) -> TicketSender<Vec<neos::SessionInfo>> {
 		self.sessions.0.clone()
 	}
 
-	pub fn auth_sender(&self) -> Sender<Arc<AnyNeos>> { self.auth.0.clone() }
+	pub fn auth_sender(&self) -> Sender<AuthMsg> { self.auth.0.clone() }
 
 	pub fn user_session_sender(&self) -> Sender<Option<neos::UserSession>> {
 		self.user_session.0.clone()
@@ -86,13 +145,13 @@ impl Channels {
 
 	pub fn image_sender(&self) -> Sender<ImageMsg> { self.image.0.clone() }
 
-	pub fn user_sender(&self) -> ResSender<neos::User> { self.user.0.clone() }
+	pub fn user_sender(&self) -> GenSender<neos::User> { self.user.0.clone() }
 
-	pub fn user_status_sender(&self) -> ResSender<UserStatusMsg> {
+	pub fn user_status_sender(&self) -> GenSender<UserStatusMsg> {
 		self.user_status.0.clone()
 	}
 
-	pub fn session_sender(&self) -> ResSender<neos::SessionInfo> {
+	pub fn session_sender(&self) -> GenSender<neos::SessionInfo> {
 		self.session.0.clone()
 	}
 
@@ -100,46 +159,80 @@ impl Channels {
 		self.update_check.0.clone()
 	}
 
-	pub fn try_recv_messages(&self) -> Option<Res<AllMessages>> {
+	pub fn notifications_sender(&self) -> Sender<Notification> {
+		self.notifications.0.clone()
+	}
+
+	pub fn realtime_sender(&self) -> Sender<realtime::Event> {
+		self.realtime.0.clone()
+	}
+
+	pub fn try_recv_refreshed_messages(&self) -> Option<Ticketed<AllMessages>> {
+		self.refreshed_messages.1.try_recv().ok()
+	}
+
+	pub fn try_recv_messages(&self) -> Option<Ticketed<AllMessages>> {
 		self.messages.1.try_recv().ok()
 	}
 
-	pub fn try_recv_friends(&self) -> Option<Res<Vec<neos::Friend>>> {
+	pub fn try_recv_older_messages(&self) -> Option<OlderMessagesMsg> {
+		self.older_messages.1.try_recv().ok()
+	}
+
+	pub fn try_recv_sent(&self) -> TryIter<'_, SentMsg> { self.sent.1.try_iter() }
+
+	pub fn try_recv_marked_read(&self) -> Option<MarkedReadMsg> {
+		self.marked_read.1.try_recv().ok()
+	}
+
+	pub fn try_recv_history(&self) -> Option<HistoryMsg> {
+		self.history.1.try_recv().ok()
+	}
+
+	pub fn try_recv_friends(&self) -> Option<Ticketed<Vec<neos::Friend>>> {
 		self.friends.1.try_recv().ok()
 	}
 
-	pub fn try_recv_users(&self) -> Option<Res<Vec<neos::User>>> {
+	pub fn try_recv_users(&self) -> Option<Ticketed<Vec<neos::User>>> {
 		self.users.1.try_recv().ok()
 	}
 
-	pub fn try_recv_sessions(&self) -> Option<Res<Vec<neos::SessionInfo>>> {
+	pub fn try_recv_sessions(&self) -> Option<Ticketed<Vec<neos::SessionInfo>>> {
 		self.sessions.1.try_recv().ok()
 	}
 
-	pub fn try_recv_auth(&self) -> Option<Arc<AnyNeos>> {
-		self.auth.1.try_recv().ok()
-	}
+	pub fn try_recv_auth(&self) -> Option<AuthMsg> { self.auth.1.try_recv().ok() }
 
 	#[allow(clippy::option_option)]
 	pub fn try_recv_user_session(&self) -> Option<Option<neos::UserSession>> {
 		self.user_session.1.try_recv().ok()
 	}
 
-	pub fn try_recv_images(&self) -> TryIter<ImageMsg> { self.image.1.try_iter() }
+	pub fn try_recv_images(&self) -> TryIter<'_, ImageMsg> {
+		self.image.1.try_iter()
+	}
 
-	pub fn try_recv_user(&self) -> Option<Res<neos::User>> {
+	pub fn try_recv_user(&self) -> Option<Gen<neos::User>> {
 		self.user.1.try_recv().ok()
 	}
 
-	pub fn try_recv_user_status(&self) -> Option<Res<UserStatusMsg>> {
+	pub fn try_recv_user_status(&self) -> Option<Gen<UserStatusMsg>> {
 		self.user_status.1.try_recv().ok()
 	}
 
-	pub fn try_recv_session(&self) -> Option<Res<neos::SessionInfo>> {
+	pub fn try_recv_session(&self) -> Option<Gen<neos::SessionInfo>> {
 		self.session.1.try_recv().ok()
 	}
 
 	pub fn try_recv_updates(&self) -> Option<GiteaReleasesResponse> {
 		self.update_check.1.try_recv().ok()
 	}
+
+	pub fn try_recv_notifications(&self) -> TryIter<'_, Notification> {
+		self.notifications.1.try_iter()
+	}
+
+	pub fn try_recv_realtime(&self) -> TryIter<'_, realtime::Event> {
+		self.realtime.1.try_iter()
+	}
 }
@@ -0,0 +1,32 @@
+use std::sync::{
+	atomic::{AtomicU64, Ordering},
+	Arc,
+};
+
+/// The generation of the latest request of a kind, so that replies to older
+/// requests can be told apart and dropped.
+#[derive(Debug, Clone, Default)]
+pub struct Generation(Arc<AtomicU64>);
+
+impl Generation {
+	/// Starts a new request, making all the previous ones stale.
+	pub fn next(&self) -> u64 { self.0.fetch_add(1, Ordering::SeqCst) + 1 }
+
+	/// Makes all the previous requests stale.
+	pub fn invalidate(&self) { self.0.fetch_add(1, Ordering::SeqCst); }
+
+	pub fn current(&self) -> u64 { self.0.load(Ordering::SeqCst) }
+
+	pub fn is_current(&self, generation: u64) -> bool {
+		self.0.load(Ordering::SeqCst) == generation
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct Generations {
+	pub user: Generation,
+	pub user_status: Generation,
+	pub session: Generation,
+	/// Of the saved messages, which are invalidated by clearing them
+	pub history: Generation,
+}
@@ -0,0 +1,162 @@
+//! A dedicated pool for loading images, so that they can't hold up the other
+//! data operations.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+};
+
+use ahash::RandomState;
+
+use crate::data::TextureKey;
+
+/// How many images are fetched and decoded at once
+const WORKERS: usize = 4;
+
+/// Higher priority jobs are started first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+	Row,
+	Window,
+}
+
+type Task = Box<dyn FnOnce() + Send>;
+
+struct Job {
+	priority: Priority,
+	/// For starting the jobs of the same priority in the order they were queued
+	order: u64,
+	/// If the image was wanted during the current frame
+	requested: bool,
+	task: Task,
+}
+
+#[derive(Default)]
+struct Queue {
+	jobs: HashMap<TextureKey, Job, RandomState>,
+	next_order: u64,
+	stopped: bool,
+}
+
+impl Queue {
+	fn take_next(&mut self) -> Option<Task> {
+		let key = self
+			.jobs
+			.iter()
+			.max_by_key(|(_, job)| (job.priority, std::cmp::Reverse(job.order)))
+			.map(|(key, _)| key.clone())?;
+
+		self.jobs.remove(&key).map(|job| job.task)
+	}
+}
+
+#[derive(Default)]
+struct Shared {
+	queue: Mutex<Queue>,
+	available: Condvar,
+}
+
+impl Shared {
+	fn lock(&self) -> MutexGuard<'_, Queue> {
+		self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+pub struct Loader {
+	shared: Arc<Shared>,
+}
+
+impl std::fmt::Debug for Loader {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.debug_struct("Loader").field("queued", &self.queued()).finish()
+	}
+}
+
+impl Default for Loader {
+	fn default() -> Self {
+		let shared = Arc::new(Shared::default());
+
+		for i in 0..WORKERS {
+			let shared = shared.clone();
+			std::thread::Builder::new()
+				.name(format!("image-{i}"))
+				.spawn(move || work(&shared))
+				.expect("Failed to spawn an image loader thread");
+		}
+
+		Self { shared }
+	}
+}
+
+impl Drop for Loader {
+	fn drop(&mut self) {
+		self.shared.lock().stopped = true;
+		self.shared.available.notify_all();
+	}
+}
+
+impl Loader {
+	/// Queues loading an image.
+	pub fn queue<OP>(&self, key: TextureKey, priority: Priority, op: OP)
+	where
+		OP: FnOnce() + Send + 'static,
+	{
+		let mut queue = self.shared.lock();
+		let order = queue.next_order;
+		queue.next_order += 1;
+		queue.jobs.insert(
+			key,
+			Job { priority, order, requested: true, task: Box::new(op) },
+		);
+		drop(queue);
+
+		self.shared.available.notify_one();
+	}
+
+	/// How many images are waiting for a worker
+	pub fn queued(&self) -> usize { self.shared.lock().jobs.len() }
+
+	/// Marks a queued image as still wanted, raising its priority if needed.
+	pub fn touch(&self, key: &TextureKey, priority: Priority) {
+		if let Some(job) = self.shared.lock().jobs.get_mut(key) {
+			job.requested = true;
+			job.priority = job.priority.max(priority);
+		}
+	}
+
+	/// Drops the queued images that weren't wanted during the frame, such as
+	/// those of rows that were scrolled out of view, returning their keys.
+	pub fn end_frame(&self) -> Vec<TextureKey> {
+		let mut dropped = Vec::new();
+		self.shared.lock().jobs.retain(|key, job| {
+			if !std::mem::take(&mut job.requested) {
+				dropped.push(key.clone());
+				return false;
+			}
+			true
+		});
+
+		dropped
+	}
+}
+
+fn work(shared: &Shared) {
+	loop {
+		let mut queue = shared.lock();
+		let task = loop {
+			if queue.stopped {
+				return;
+			}
+			if let Some(task) = queue.take_next() {
+				break task;
+			}
+			queue =
+				shared.available.wait(queue).unwrap_or_else(PoisonError::into_inner);
+		};
+		drop(queue);
+
+		if std::panic::catch_unwind(std::panic::AssertUnwindSafe(task)).is_err() {
+			log::error!("Image loader thread panicked");
+		}
+	}
+}
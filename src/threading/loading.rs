@@ -1,25 +1,125 @@
-use std::cell::Cell;
+use std::{
+	cell::{Cell, RefCell},
+	time::{Duration, Instant},
+};
+
+/// Identifies an operation, so that finishing it can't finish another one
+pub type Ticket = u64;
+
+/// The operations of a kind that are in progress
+#[derive(Debug, Default)]
+pub struct Counter {
+	next_ticket: Cell<Ticket>,
+	/// When each of the operations started, oldest first
+	started: RefCell<Vec<(Ticket, Instant)>>,
+}
+
+impl Counter {
+	/// Returns the ticket to finish the operation with
+	pub fn start(&self) -> Ticket {
+		let ticket = self.next_ticket.get();
+		self.next_ticket.set(ticket.wrapping_add(1));
+		self.started.borrow_mut().push((ticket, Instant::now()));
+		ticket
+	}
+
+	/// Starts an operation that replaces all the previous ones
+	pub fn restart(&self) {
+		self.reset();
+		self.start();
+	}
+
+	/// Does nothing if the operation was already forgotten, such as when it
+	/// expired
+	pub fn finish(&self, ticket: Ticket) {
+		self.started.borrow_mut().retain(|(started, _)| *started != ticket);
+	}
+
+	pub fn reset(&self) { self.started.borrow_mut().clear(); }
+
+	pub fn count(&self) -> usize { self.started.borrow().len() }
+
+	pub fn is_loading(&self) -> bool { !self.started.borrow().is_empty() }
+
+	/// Forgets the operations that have been running for too long, returning
+	/// how many there were
+	fn expire(&self, timeout: Duration) -> usize {
+		let mut started = self.started.borrow_mut();
+		let before = started.len();
+		started.retain(|(_, start)| start.elapsed() < timeout);
+		before - started.len()
+	}
+}
 
-#[allow(clippy::struct_excessive_bools)]
 #[derive(Debug, Default)]
 pub struct Tracker {
-	pub friends: Cell<bool>,
-	pub users: Cell<bool>,
-	pub sessions: Cell<bool>,
-	pub user: Cell<bool>,
-	pub user_status: Cell<bool>,
-	pub session: Cell<bool>,
-	pub messages: Cell<bool>,
+	pub friends: Counter,
+	pub users: Counter,
+	pub sessions: Counter,
+	pub user: Counter,
+	pub user_status: Counter,
+	pub session: Counter,
+	pub messages: Counter,
 }
 
 impl Tracker {
+	const fn counters(&self) -> [(&'static str, &Counter); 7] {
+		[
+			("friends", &self.friends),
+			("users", &self.users),
+			("sessions", &self.sessions),
+			("user", &self.user),
+			("user status", &self.user_status),
+			("session", &self.session),
+			("messages", &self.messages),
+		]
+	}
+
 	pub fn any(&self) -> bool {
-		self.friends.get()
-			|| self.users.get()
-			|| self.sessions.get()
-			|| self.user.get()
-			|| self.user_status.get()
-			|| self.session.get()
-			|| self.messages.get()
+		self.counters().iter().any(|(_, counter)| counter.is_loading())
+	}
+
+	pub fn count(&self) -> usize {
+		self.counters().iter().map(|(_, counter)| counter.count()).sum()
+	}
+
+	/// Something like `friends: 1, messages: 2`
+	pub fn summary(&self) -> String {
+		self
+			.counters()
+			.iter()
+			.filter(|(_, counter)| counter.is_loading())
+			.map(|(name, counter)| format!("{name}: {}", counter.count()))
+			.collect::<Vec<String>>()
+			.join(", ")
+	}
+
+	/// Forgets the operations that are most likely stuck, such as ones that
+	/// panicked, returning the kinds & amounts of them
+	pub fn expire(&self, timeout: Duration) -> Vec<(&'static str, usize)> {
+		self
+			.counters()
+			.iter()
+			.map(|(name, counter)| (*name, counter.expire(timeout)))
+			.filter(|(_, expired)| *expired > 0)
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn stale_finish_is_ignored() {
+		let counter = Counter::default();
+		let stale = counter.start();
+		assert_eq!(counter.expire(Duration::ZERO), 1);
+
+		let current = counter.start();
+		counter.finish(stale);
+		assert!(counter.is_loading());
+		counter.finish(current);
+		assert!(!counter.is_loading());
 	}
 }
@@ -1,19 +1,40 @@
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 
 use eframe::egui::Context;
 use rayon::ThreadPool;
 
 mod channels;
+mod generations;
+pub mod images;
 mod loading;
+mod schedule;
 
 use channels::Channels;
+pub use loading::Ticket;
 
-use crate::app::NeosPeepsApp;
+use crate::{
+	app::NeosPeepsApp,
+	data::OlderMessages,
+	error::PeepsError,
+	messages::{AllMessages, MESSAGES_PAGE},
+	notifications::{Notification, Severity},
+	realtime,
+};
+
+/// How much extra to wait before the next background refreshes when rate
+/// limited
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(60);
+/// After how long an operation is assumed to be stuck, the API requests
+/// themselves time out after 2 minutes.
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(180);
 
 #[derive(Debug)]
 pub struct Manager {
 	pub channels: Channels,
 	pub loading: loading::Tracker,
+	pub generations: generations::Generations,
+	pub schedules: schedule::Schedules,
+	pub images: images::Loader,
 	data: ThreadPool,
 	// Also logout operations
 	login: ThreadPool,
@@ -21,19 +42,33 @@ pub struct Manager {
 
 impl Default for Manager {
 	fn default() -> Self {
+		let channels = Channels::default();
+		let data_notifications = channels.notifications_sender();
+		let login_notifications = channels.notifications_sender();
+
 		Self {
-			channels: Channels::default(),
+			channels,
 			loading: loading::Tracker::default(),
+			generations: generations::Generations::default(),
+			schedules: schedule::Schedules::default(),
+			images: images::Loader::default(),
 			data: rayon::ThreadPoolBuilder::new()
 				.panic_handler(move |m| {
-					eprintln!("WARNING: Data thread panicked! {m:?}");
+					data_notifications
+						.send(Notification::error("Data thread panicked", format!("{m:?}")))
+						.ok();
 				})
 				.build()
 				.unwrap(),
 			login: rayon::ThreadPoolBuilder::new()
 				.num_threads(1)
 				.panic_handler(move |m| {
-					eprintln!("WARNING: Login thread panicked! {m:?}");
+					login_notifications
+						.send(Notification::error(
+							"Login thread panicked",
+							format!("{m:?}"),
+						))
+						.ok();
 				})
 				.build()
 				.unwrap(),
@@ -51,7 +86,11 @@ impl Manager {
 			std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
 				self.login.spawn(op);
 			})) {
-			eprintln!("WARNING: Login thread panicked! {e:?}");
+			self
+				.channels
+				.notifications_sender()
+				.send(Notification::error("Login thread panicked", format!("{e:?}")))
+				.ok();
 		}
 	}
 
@@ -64,7 +103,11 @@ impl Manager {
 			std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
 				self.data.spawn_fifo(op);
 			})) {
-			eprintln!("WARNING: Data thread panicked! {e:?}");
+			self
+				.channels
+				.notifications_sender()
+				.send(Notification::error("Data thread panicked", format!("{e:?}")))
+				.ok();
 		}
 	}
 }
@@ -75,13 +118,19 @@ impl NeosPeepsApp {
 		let mut repaint = false;
 
 		self.try_recv_auth(&mut repaint);
-		self.try_recv_lists(&mut repaint);
-		self.try_recv_window(&mut repaint);
+		self.try_recv_lists(ctx, &mut repaint);
+		self.try_recv_window(ctx, &mut repaint);
+		self.try_recv_realtime(&mut repaint);
 
-		for (id, image) in self.threads.channels.try_recv_images() {
+		let images: Vec<_> = self.threads.channels.try_recv_images().collect();
+		for (id, image) in images {
 			self.runtime.loading_textures.get_mut().remove(&id);
-			if let Some(image) = image {
-				self.runtime.textures.insert(id, Rc::new(image));
+			match image {
+				Ok(image) => {
+					self.runtime.failed_images.remove(&id.0);
+					self.runtime.textures.insert(id, Rc::new(image));
+				}
+				Err(err) => self.image_failed(id.0, err),
 			}
 			repaint = true;
 		}
@@ -90,6 +139,19 @@ impl NeosPeepsApp {
 			self.runtime.available_update = Some(latest_ver);
 		}
 
+		for notification in self.threads.channels.try_recv_notifications() {
+			self.runtime.notifications.push(notification);
+			repaint = true;
+		}
+
+		for (kind, amount) in self.threads.loading.expire(OPERATION_TIMEOUT) {
+			self.runtime.notifications.push(Notification::warning(
+				format!("Loading {kind} timed out"),
+				format!("Gave up waiting on {amount} operation(s)"),
+			));
+			repaint = true;
+		}
+
 		if repaint {
 			ctx.request_repaint();
 		}
@@ -97,9 +159,16 @@ impl NeosPeepsApp {
 
 	fn try_recv_auth(&mut self, repaint: &mut bool) {
 		if let Some(user_session) = self.threads.channels.try_recv_user_session() {
+			if user_session.is_none() {
+				self.remove_snapshot();
+			}
+			let logged_in = user_session.is_some();
 			self.stored.user_session = user_session;
-			*self.runtime.session_window.borrow_mut() = None;
-			*self.runtime.user_window.borrow_mut() = None;
+			if logged_in {
+				self.load_message_history();
+			}
+			self.close_session_window();
+			self.close_user_window();
 		}
 
 		if let Some(client) = self.threads.channels.try_recv_auth() {
@@ -108,66 +177,178 @@ impl NeosPeepsApp {
 		}
 	}
 
-	fn try_recv_lists(&mut self, repaint: &mut bool) {
-		if let Some(res) = self.threads.channels.try_recv_friends() {
-			self.threads.loading.friends.set(false);
+	fn try_recv_realtime(&mut self, repaint: &mut bool) {
+		let events: Vec<_> = self.threads.channels.try_recv_realtime().collect();
+		for event in events {
+			match event {
+				realtime::Event::Connected => {
+					if let Some(realtime) = &mut self.runtime.realtime {
+						realtime.connected = true;
+					}
+					// Catch up on anything missed while not connected
+					self.threads.schedules.friends.force();
+					self.threads.schedules.messages.force();
+				}
+				realtime::Event::Disconnected(reason) => {
+					if let Some(realtime) = &mut self.runtime.realtime {
+						realtime.connected = false;
+					}
+					self.runtime.notifications.push(Notification::warning(
+						"Realtime connection lost, falling back to polling",
+						reason,
+					));
+				}
+				realtime::Event::Status(user_id, status) => {
+					self.apply_user_status(&user_id, status);
+				}
+				realtime::Event::Message(message) => {
+					let messages = Self::split_by_user(vec![message]);
+					self.store_messages(&messages);
+					self.merge_messages(messages);
+				}
+			}
+			*repaint = true;
+		}
+	}
+
+	fn try_recv_lists(&mut self, ctx: &Context, repaint: &mut bool) {
+		if let Some((ticket, res)) = self.threads.channels.try_recv_friends() {
+			self.threads.loading.friends.finish(ticket);
 			match res {
 				Ok(friends) => {
+					self.threads.schedules.friends.succeeded();
 					self.runtime.friends = friends;
+					self.runtime.data_revision += 1;
+					self.runtime.stale_since = None;
+					self.save_snapshot();
 					*repaint = true;
 				}
-				Err(e) => eprintln!("Failed to fetch friends! {e}"),
+				Err(e) => {
+					self.threads.schedules.friends.failed();
+					self.handle_fetch_error(ctx, "friends", &e);
+				}
 			}
 		}
 
-		if let Some(res) = self.threads.channels.try_recv_users() {
-			self.threads.loading.users.set(false);
+		if let Some((ticket, res)) = self.threads.channels.try_recv_users() {
+			self.threads.loading.users.finish(ticket);
 			match res {
 				Ok(users) => {
 					self.runtime.users = users;
 					*repaint = true;
 				}
-				Err(e) => eprintln!("Failed to fetch users! {e}"),
+				Err(e) => self.handle_fetch_error(ctx, "users", &e),
 			}
 		}
 
-		if let Some(res) = self.threads.channels.try_recv_sessions() {
-			self.threads.loading.sessions.set(false);
+		if let Some((ticket, res)) = self.threads.channels.try_recv_sessions() {
+			self.threads.loading.sessions.finish(ticket);
 			match res {
 				Ok(sessions) => {
+					self.threads.schedules.sessions.succeeded();
 					self.runtime.sessions = sessions;
+					self.save_snapshot();
 					*repaint = true;
 				}
-				Err(e) => eprintln!("Failed to fetch sessions! {e}"),
+				Err(e) => {
+					self.threads.schedules.sessions.failed();
+					self.handle_fetch_error(ctx, "sessions", &e);
+				}
 			}
 		}
 
-		if let Some(res) = self.threads.channels.try_recv_messages() {
-			self.threads.loading.messages.set(false);
-			match res {
-				Ok(messages) => {
-					for (user_id, fetched_messages) in messages {
-						if let Some(stored_messages) =
-							self.runtime.messages.get_mut(&user_id)
-						{
-							for message in fetched_messages.into_vec() {
-								stored_messages.replace(message);
-							}
-						} else {
-							self.runtime.messages.insert(user_id, fetched_messages);
-						}
-					}
+		if let Some((user_id, res)) =
+			self.threads.channels.try_recv_older_messages()
+		{
+			self.receive_older_messages(ctx, user_id, res);
+			*repaint = true;
+		}
 
+		let sent: Vec<_> = self.threads.channels.try_recv_sent().collect();
+		for (id, res) in sent {
+			self.message_sent(&id, res);
+			*repaint = true;
+		}
+
+		while let Some((user_id, res)) =
+			self.threads.channels.try_recv_marked_read()
+		{
+			match res {
+				Ok(ids) => {
+					self.runtime.marking_read.get_mut().remove(&user_id);
+					self.set_read(&user_id, &ids);
 					*repaint = true;
 				}
-				Err(e) => eprintln!("Failed to fetch messages! {e}"),
+				// Not retried until the chat is opened again
+				Err(e) => {
+					self.runtime.notifications.push(Notification::error(
+						"Failed to mark messages as read",
+						e.to_string(),
+					));
+				}
+			}
+		}
+
+		// Loaded before the history was cleared
+		if let Some((_, messages)) =
+			self.threads.channels.try_recv_history().filter(|(generation, _)| {
+				self.threads.generations.history.is_current(*generation)
+			}) {
+			self.merge_messages(messages);
+			*repaint = true;
+		}
+
+		// Only the scheduled refreshes affect when the next one happens
+		if let Some((ticket, res)) =
+			self.threads.channels.try_recv_refreshed_messages()
+		{
+			if self.receive_messages(ctx, ticket, res) {
+				self.threads.schedules.messages.succeeded();
+			} else {
+				self.threads.schedules.messages.failed();
+			}
+			*repaint = true;
+		}
+
+		if let Some((ticket, res)) = self.threads.channels.try_recv_messages() {
+			self.receive_messages(ctx, ticket, res);
+			*repaint = true;
+		}
+	}
+
+	/// Returns if the messages were fetched successfully
+	fn receive_messages(
+		&mut self, ctx: &Context, ticket: Ticket,
+		res: Result<AllMessages, PeepsError>,
+	) -> bool {
+		self.threads.loading.messages.finish(ticket);
+		match res {
+			Ok(messages) => {
+				self.store_messages(&messages);
+				if self.merge_messages(messages) {
+					self.save_snapshot();
+				}
+				true
+			}
+			Err(e) => {
+				self.handle_fetch_error(ctx, "messages", &e);
+				false
 			}
 		}
 	}
 
-	fn try_recv_window(&mut self, repaint: &mut bool) {
-		if let Some(res) = self.threads.channels.try_recv_user() {
-			self.threads.loading.user.set(false);
+	fn try_recv_window(&mut self, ctx: &Context, repaint: &mut bool) {
+		// Replies to requests that have since been replaced or cancelled are
+		// dropped
+		if let Some((_, res)) =
+			self.threads.channels.try_recv_user().filter(|(generation, _)| {
+				let current = self.threads.generations.user.is_current(*generation);
+				if !current {
+					log::debug!("Dropped a stale user reply");
+				}
+				current
+			}) {
+			self.threads.loading.user.reset();
 			match res {
 				Ok(user) => {
 					if let Some((user_id, w_user, _)) =
@@ -179,12 +360,20 @@ impl NeosPeepsApp {
 					}
 					*repaint = true;
 				}
-				Err(e) => eprintln!("Failed to fetch user! {e}"),
+				Err(e) => self.handle_fetch_error(ctx, "user", &e),
 			}
 		}
 
-		if let Some(res) = self.threads.channels.try_recv_user_status() {
-			self.threads.loading.user_status.set(false);
+		if let Some((_, res)) =
+			self.threads.channels.try_recv_user_status().filter(|(generation, _)| {
+				let current =
+					self.threads.generations.user_status.is_current(*generation);
+				if !current {
+					log::debug!("Dropped a stale user status reply");
+				}
+				current
+			}) {
+			self.threads.loading.user_status.reset();
 			match res {
 				Ok((user_id, user_status)) => {
 					if let Some((w_user_id, _, w_user_status)) =
@@ -196,12 +385,19 @@ impl NeosPeepsApp {
 					}
 					*repaint = true;
 				}
-				Err(e) => eprintln!("Failed to fetch user! {e}"),
+				Err(e) => self.handle_fetch_error(ctx, "user status", &e),
 			}
 		}
 
-		if let Some(res) = self.threads.channels.try_recv_session() {
-			self.threads.loading.session.set(false);
+		if let Some((_, res)) =
+			self.threads.channels.try_recv_session().filter(|(generation, _)| {
+				let current = self.threads.generations.session.is_current(*generation);
+				if !current {
+					log::debug!("Dropped a stale session reply");
+				}
+				current
+			}) {
+			self.threads.loading.session.reset();
 			match res {
 				Ok(session) => {
 					if let Some((session_id, w_session)) =
@@ -213,8 +409,80 @@ impl NeosPeepsApp {
 					}
 					*repaint = true;
 				}
-				Err(e) => eprintln!("Failed to fetch user! {e}"),
+				Err(e) => self.handle_fetch_error(ctx, "session", &e),
+			}
+		}
+	}
+
+	/// Adds a page of older messages, or marks the beginning of the
+	/// conversation if the page wasn't full.
+	fn receive_older_messages(
+		&mut self, ctx: &Context, user_id: neos::id::User,
+		res: Result<Vec<neos::Message>, PeepsError>,
+	) {
+		let page = match res {
+			Ok(page) => page,
+			Err(e) => {
+				self.runtime.older_messages.get_mut().remove(&user_id);
+				self.handle_fetch_error(ctx, "older messages", &e);
+				return;
+			}
+		};
+		let is_last_page = page.len() < usize::from(MESSAGES_PAGE);
+		let known = self.runtime.messages.get(&user_id);
+		let new = page
+			.iter()
+			.filter(|message| {
+				known.is_none_or(|known| !known.iter().any(|m| m.0.id == message.id))
+			})
+			.count();
+
+		let messages = Self::split_by_user(page);
+		let count =
+			|app: &Self| app.runtime.messages.get(&user_id).map_or(0, |m| m.len());
+		let before = count(self);
+		self.store_messages(&messages);
+		self.merge_messages(messages);
+		let added = count(self).saturating_sub(before);
+
+		let state = if is_last_page {
+			OlderMessages::Beginning
+		} else if added > 0 {
+			OlderMessages::Added(added)
+		} else if new > 0 {
+			// Paging further would get the same page again
+			OlderMessages::NotKept
+		} else {
+			// Nothing older than the already loaded messages was returned
+			OlderMessages::Beginning
+		};
+		self.runtime.older_messages.get_mut().insert(user_id, state);
+	}
+
+	/// Reacts to a failed background fetch depending on what went wrong.
+	fn handle_fetch_error(
+		&mut self, ctx: &Context, what: &str, err: &PeepsError,
+	) {
+		self.runtime.notifications.push(Notification::new(
+			match err {
+				PeepsError::RateLimited(_) => Severity::Warning,
+				_ => Severity::Error,
+			},
+			format!("Failed to fetch {what}"),
+			err.to_string(),
+		));
+
+		match err {
+			// A login operation being in progress already is indicated by no API
+			PeepsError::AuthExpired(_) if self.runtime.neos_api.is_some() => {
+				if let Some(user_session) = self.stored.user_session.clone() {
+					self.try_use_session(user_session, ctx);
+				}
+			}
+			PeepsError::RateLimited(_) => {
+				self.threads.schedules.delay_all(RATE_LIMIT_BACKOFF);
 			}
+			_ => {}
 		}
 	}
 }
@@ -0,0 +1,70 @@
+use std::time::{Duration, SystemTime};
+
+/// The most that the refresh interval is multiplied by after failures
+const MAX_BACKOFF_MULTIPLIER: u32 = 16;
+
+/// When a resource should next be refreshed in the background
+#[derive(Debug)]
+pub struct Schedule {
+	/// When the refresh was last started
+	last_refresh: SystemTime,
+	/// How many refreshes have failed in a row
+	failures: u32,
+}
+
+impl Default for Schedule {
+	fn default() -> Self {
+		Self { last_refresh: SystemTime::UNIX_EPOCH, failures: 0 }
+	}
+}
+
+impl Schedule {
+	pub fn is_due(&self, interval: Duration) -> bool {
+		self.last_refresh + self.backoff(interval) < SystemTime::now()
+	}
+
+	/// The interval, doubled for each failure in a row
+	fn backoff(&self, interval: Duration) -> Duration {
+		interval * 2_u32.saturating_pow(self.failures).min(MAX_BACKOFF_MULTIPLIER)
+	}
+
+	pub fn started(&mut self) { self.last_refresh = SystemTime::now(); }
+
+	pub const fn succeeded(&mut self) { self.failures = 0; }
+
+	pub const fn failed(&mut self) {
+		self.failures = self.failures.saturating_add(1);
+	}
+
+	/// Makes the refresh happen as soon as possible
+	pub const fn force(&mut self) {
+		self.last_refresh = SystemTime::UNIX_EPOCH;
+		self.failures = 0;
+	}
+
+	/// Pushes the next refresh further away
+	pub fn delay(&mut self, by: Duration) {
+		self.last_refresh = self.last_refresh.max(SystemTime::now()) + by;
+	}
+}
+
+#[derive(Debug, Default)]
+pub struct Schedules {
+	pub friends: Schedule,
+	pub sessions: Schedule,
+	pub messages: Schedule,
+}
+
+impl Schedules {
+	pub const fn force_all(&mut self) {
+		self.friends.force();
+		self.sessions.force();
+		self.messages.force();
+	}
+
+	pub fn delay_all(&mut self, by: Duration) {
+		self.friends.delay(by);
+		self.sessions.delay(by);
+		self.messages.delay(by);
+	}
+}
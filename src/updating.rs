@@ -3,7 +3,7 @@ use std::time::SystemTime;
 use eframe::egui::{Context, Id, Window};
 use serde::Deserialize;
 
-use crate::app::NeosPeepsApp;
+use crate::{app::NeosPeepsApp, notifications::Notification};
 
 const UPDATE_CHECK_BASE: &str =
 	"https://git.ljoonal.xyz/api/v1/repos/ljoonal/NeosPeeps/";
@@ -36,21 +36,31 @@ impl NeosPeepsApp {
 	pub fn check_updates(&mut self) {
 		self.stored.last_update_check_time = SystemTime::now();
 		let update_check_sender = self.threads.channels.update_check_sender();
+		let notifications_sender = self.threads.channels.notifications_sender();
 
 		// .... This is just clearer, plain and simple.
 		#[allow(clippy::option_if_let_else)]
 		std::thread::spawn(move || {
+			log::debug!("Checking for updates");
 			let res = latest_version_request();
 			if let Ok(rel) = res {
 				if let Some(rel_v) = rel.tag_name.strip_prefix('v') {
 					if rel_v != env!("CARGO_PKG_VERSION") {
+						log::info!("Update available: {}", rel.tag_name);
 						update_check_sender.send(rel).unwrap();
 					}
 				} else {
-					eprintln!("Update check returned wrongly formatted tag name!");
+					notifications_sender
+						.send(Notification::warning(
+							"Update check returned wrongly formatted tag name",
+							rel.tag_name,
+						))
+						.ok();
 				}
 			} else if let Err(err) = res {
-				eprintln!("{err}");
+				notifications_sender
+					.send(Notification::warning("Failed to check for updates", err))
+					.ok();
 			}
 		});
 	}
@@ -59,7 +69,7 @@ impl NeosPeepsApp {
 fn latest_version_request() -> Result<GiteaReleasesResponse, String> {
 	let url = UPDATE_CHECK_BASE.to_owned() + "releases";
 
-	let res = minreq::get(url.to_string())
+	let res = minreq::get(url.clone())
 		.with_header("User-Agent", crate::USER_AGENT)
 		.with_param("draft", "false")
 		.with_param("pre-release", "false")
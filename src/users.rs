@@ -1,60 +1,66 @@
 //! The friends page of the app
 
-use std::{cmp::Ordering, rc::Rc, sync::Arc};
+use std::{cmp::Ordering, sync::Arc};
 
 use crossbeam::channel::Sender;
 use eframe::egui::{Context, TextureHandle};
-use neos::api_client::{AnyNeos, Neos};
 
-use crate::app::NeosPeepsApp;
+use crate::{
+	app::NeosPeepsApp,
+	backend::NeosBackend,
+	data::SizeTier,
+	error::PeepsError,
+	notifications::Notification,
+	threading::Ticket,
+};
 
 fn order_users(s1: &neos::UserStatus, s2: &neos::UserStatus) -> Ordering {
 	// if their current session is joinable
 	if s1.current_session_access_level > s2.current_session_access_level {
 		return Ordering::Less;
-	};
+	}
 	if s1.current_session_access_level < s2.current_session_access_level {
 		return Ordering::Greater;
-	};
+	}
 
 	// if the friends are marked as online
 	if s1.online_status == neos::OnlineStatus::Online
 		&& s2.online_status != neos::OnlineStatus::Online
 	{
 		return Ordering::Less;
-	};
+	}
 	if s1.online_status != neos::OnlineStatus::Online
 		&& s2.online_status == neos::OnlineStatus::Online
 	{
 		return Ordering::Greater;
-	};
+	}
 
 	// if at least not offline
 	if s1.online_status != neos::OnlineStatus::Offline
 		&& s2.online_status == neos::OnlineStatus::Offline
 	{
 		return Ordering::Less;
-	};
+	}
 	if s1.online_status == neos::OnlineStatus::Offline
 		&& s2.online_status != neos::OnlineStatus::Offline
 	{
 		return Ordering::Greater;
-	};
+	}
 	Ordering::Equal
 }
 
 impl NeosPeepsApp {
 	/// Refreshes friends in a background thread
-	pub fn refresh_friends(&mut self, ctx: &Context) {
-		let neos_api_arc = match &self.runtime.neos_api {
+	pub fn refresh_friends(&self, ctx: &Context) {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 
-		self.threads.loading.friends.set(true);
+		let ticket = self.threads.loading.friends.start();
 		let friends_sender = self.threads.channels.friends_sender();
 		self.threads.spawn_data_op(move || {
-			Self::fetch_friends(neos_api_arc, friends_sender);
+			Self::fetch_friends(neos_api, ticket, friends_sender);
 		});
 
 		ctx.request_repaint();
@@ -62,34 +68,32 @@ impl NeosPeepsApp {
 
 	#[allow(clippy::needless_pass_by_value)]
 	fn fetch_friends(
-		neos_api_arc: Arc<AnyNeos>,
-		friends_sender: Sender<Result<Vec<neos::Friend>, String>>,
+		neos_api: Arc<dyn NeosBackend>, ticket: Ticket,
+		friends_sender: Sender<(Ticket, Result<Vec<neos::Friend>, PeepsError>)>,
 	) {
-		if let AnyNeos::Authenticated(neos_api) = &*neos_api_arc {
-			match neos_api.get_friends(None) {
-				Ok(mut friends) => {
-					friends.sort_by(|f1, f2| order_users(&f1.status, &f2.status));
-					friends_sender.send(Ok(friends)).unwrap();
-				}
-				Err(e) => {
-					friends_sender.send(Err(e.to_string())).unwrap();
-				}
+		match neos_api.get_friends() {
+			Ok(mut friends) => {
+				friends.sort_by(|f1, f2| order_users(&f1.status, &f2.status));
+				friends_sender.send((ticket, Ok(friends))).unwrap();
+			}
+			Err(e) => {
+				friends_sender.send((ticket, Err(e.into()))).unwrap();
 			}
 		}
 	}
 
-	pub fn search_users(&mut self, ctx: &Context) {
+	pub fn search_users(&self, ctx: &Context) {
 		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 
-		self.threads.loading.users.set(true);
+		let ticket = self.threads.loading.users.start();
 		let users_sender = self.threads.channels.users_sender();
 		let search = self.stored.filter_search.clone();
 		self.threads.spawn_data_op(move || {
-			let res = neos_api.search_users(search);
-			users_sender.send(res.map_err(|e| e.to_string())).unwrap();
+			let res = neos_api.search_users(&search);
+			users_sender.send((ticket, res.map_err(PeepsError::from))).unwrap();
 		});
 
 		ctx.request_repaint();
@@ -110,50 +114,66 @@ impl NeosPeepsApp {
 			*self.runtime.user_window.borrow_mut() = Some((id.clone(), None, None));
 		}
 
-		self.threads.loading.user.set(true);
+		self.threads.loading.user.restart();
 
 		let id = id.clone();
 		let user_sender = self.threads.channels.user_sender();
+		let generation = self.threads.generations.user.clone();
+		let current = generation.next();
 		self.threads.spawn_data_op(move || {
-			let res = neos_api.get_user(id);
-			user_sender.send(res.map_err(|e| e.to_string())).unwrap();
+			// Was cancelled or replaced while waiting in the queue
+			if !generation.is_current(current) {
+				return;
+			}
+			let res = neos_api.get_user(&id);
+			user_sender.send((current, res.map_err(PeepsError::from))).unwrap();
 		});
 		ctx.request_repaint();
 	}
 
 	/// Sends a friend request
 	pub fn add_friend(&self, id: neos::id::User) {
-		let neos_api_arc = match &self.runtime.neos_api {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 
+		let ticket = self.threads.loading.friends.start();
 		let friends_sender = self.threads.channels.friends_sender();
+		let notifications_sender = self.threads.channels.notifications_sender();
 		self.threads.spawn_data_op(move || {
-			if let AnyNeos::Authenticated(neos_api) = &*neos_api_arc {
-				if let Err(err) = neos_api.add_friend(id) {
-					eprintln!("Failed to send friend request: {err:?}");
-				}
-				Self::fetch_friends(neos_api_arc, friends_sender);
+			if let Err(err) = neos_api.add_friend(&id) {
+				notifications_sender
+					.send(Notification::error(
+						"Failed to send friend request",
+						PeepsError::from(err).to_string(),
+					))
+					.ok();
 			}
+			Self::fetch_friends(neos_api, ticket, friends_sender);
 		});
 	}
 
 	/// Sends a friend removal request
 	pub fn remove_friend(&self, id: neos::id::User) {
-		let neos_api_arc = match &self.runtime.neos_api {
+		let neos_api = match &self.runtime.neos_api {
 			Some(api) => api.clone(),
 			None => return,
 		};
 
+		let ticket = self.threads.loading.friends.start();
 		let friends_sender = self.threads.channels.friends_sender();
+		let notifications_sender = self.threads.channels.notifications_sender();
 		self.threads.spawn_data_op(move || {
-			if let AnyNeos::Authenticated(neos_api) = &*neos_api_arc {
-				if let Err(err) = neos_api.remove_friend(id) {
-					eprintln!("Failed to send friend removal request: {err:?}");
-				}
-				Self::fetch_friends(neos_api_arc, friends_sender);
+			if let Err(err) = neos_api.remove_friend(&id) {
+				notifications_sender
+					.send(Notification::error(
+						"Failed to send friend removal request",
+						PeepsError::from(err).to_string(),
+					))
+					.ok();
 			}
+			Self::fetch_friends(neos_api, ticket, friends_sender);
 		});
 	}
 
@@ -171,17 +191,21 @@ impl NeosPeepsApp {
 			*self.runtime.user_window.borrow_mut() = Some((id.clone(), None, None));
 		}
 
-		self.threads.loading.user_status.set(true);
+		self.threads.loading.user_status.restart();
 
 		let id = id.clone();
 		let user_status_sender = self.threads.channels.user_status_sender();
+		let generation = self.threads.generations.user_status.clone();
+		let current = generation.next();
 		self.threads.spawn_data_op(move || {
-			match neos_api.get_user_status(id.clone()) {
-				Ok(user_status) => {
-					user_status_sender.send(Ok((id, user_status))).unwrap();
-				}
-				Err(e) => user_status_sender.send(Err(e.to_string())).unwrap(),
+			if !generation.is_current(current) {
+				return;
 			}
+			let res = neos_api.get_user_status(&id);
+			let res = res.map(|user_status| (id, user_status));
+			user_status_sender
+				.send((current, res.map_err(PeepsError::from)))
+				.unwrap();
 		});
 
 		ctx.request_repaint();
@@ -196,10 +220,11 @@ impl NeosPeepsApp {
 
 		match &mut self.runtime.user_window.try_borrow_mut() {
 			Ok(user_window) => {
+				self.cancel_user_requests();
 				**user_window = Some((id.clone(), user, user_status));
 			}
-			Err(err) => eprintln!("Failed to open user: {err:?}"),
-		};
+			Err(err) => log::error!("Failed to open user: {err:?}"),
+		}
 
 		if missing_user {
 			self.get_user(ctx, id);
@@ -209,15 +234,54 @@ impl NeosPeepsApp {
 		}
 	}
 
+	/// Makes the replies to the user window's pending requests be ignored
+	fn cancel_user_requests(&self) {
+		self.threads.generations.user.invalidate();
+		self.threads.generations.user_status.invalidate();
+		self.threads.loading.user.reset();
+		self.threads.loading.user_status.reset();
+	}
+
+	pub fn close_user_window(&self) {
+		self.cancel_user_requests();
+		*self.runtime.user_window.borrow_mut() = None;
+	}
+
 	pub fn get_pfp(
-		&self, ctx: &Context, profile: &Option<neos::UserProfile>,
-	) -> Rc<TextureHandle> {
-		let pfp_url = profile.as_ref().map_or(&None, |profile| &profile.icon_url);
-		let pfp = pfp_url
-			.as_ref()
-			.map_or_else(|| None, |pfp_url| self.load_texture(pfp_url, ctx));
-
-		pfp.unwrap_or_else(|| self.runtime.default_profile_picture.clone().unwrap())
+		&self, ctx: &Context, profile: Option<&neos::UserProfile>, tier: SizeTier,
+	) -> TextureHandle {
+		let pfp_url = profile.and_then(|profile| profile.icon_url.as_ref());
+		let pfp = pfp_url.and_then(|pfp_url| self.load_texture(pfp_url, tier, ctx));
+
+		pfp.unwrap_or_else(|| {
+			TextureHandle::clone(
+				self.runtime.default_profile_picture.as_ref().unwrap(),
+			)
+		})
+	}
+
+	/// Updates the status of a friend and the user window, from a realtime
+	/// push for example.
+	pub fn apply_user_status(
+		&mut self, user_id: &neos::id::User, status: neos::UserStatus,
+	) {
+		if let Some((w_user_id, _, w_user_status)) =
+			&mut *self.runtime.user_window.borrow_mut()
+		{
+			if w_user_id == user_id {
+				*w_user_status = Some(status.clone());
+			}
+		}
+
+		if let Some(friend) =
+			self.runtime.friends.iter_mut().find(|friend| friend.id == *user_id)
+		{
+			friend.status = status;
+			self
+				.runtime
+				.friends
+				.sort_by(|f1, f2| order_users(&f1.status, &f2.status));
+		}
 	}
 
 	pub fn user_to_friend(&self, user: &neos::User) -> Option<&neos::Friend> {